@@ -6,6 +6,7 @@ use hyper;
 use std::sync::PoisonError;
 use regex;
 use url;
+use rusqlite;
 use std::net;
 
 use std::error::Error as StdErr;
@@ -22,9 +23,26 @@ pub enum Error {
     UrlParseError(url::ParseError),
     UnforeseenError,
     ServerError(hyper::Error),
+    /// Forwarding a captured request to a bin's upstream failed.
+    UpstreamError(hyper::Error),
     AddressParsingErr(net::AddrParseError),
+    /// A storage backend failed to carry out an operation on Bins.
+    StorageError(Box<dyn StdErr + Send + Sync>),
     FromUtf8Error,
     HyperError,
+    /// Not a failure as such: a bin's faults asked for the connection to be dropped without
+    /// responding.
+    ConnectionDropped,
+}
+
+impl Error {
+    /// Wraps any error coming out of a storage backend.
+    pub fn storage<E>(e: E) -> Error
+    where
+        E: Into<Box<dyn StdErr + Send + Sync>>,
+    {
+        Error::StorageError(e.into())
+    }
 }
 
 impl<T> From<PoisonError<T>> for Error {
@@ -69,22 +87,30 @@ impl From<net::AddrParseError> for Error {
     }
 }
 
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::storage(e)
+    }
+}
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // Use `self.number` to refer to each positional data point.
         use self::Error::*;
-        match self {
-            &PoisonedLock => write!(f, "Poisoned lock error"),
-            &UnforeseenError => write!(f, "Unforeseen error"),
-            &FromUtf8Error => write!(f, "From UTF8 error"),
-            &HyperError => write!(f, "Hyper error"),
-            &AddressParsingErr(ref e) => e.fmt(f),
-            &JsonEncodingError(ref e) => e.fmt(f),
-            &IOError(ref e) => e.fmt(f),
-            &RegexError(ref e) => e.fmt(f),
-            &UrlParseError(ref e) => e.fmt(f),
-            &ServerError(ref e) => e.fmt(f),
+        match *self {
+            PoisonedLock => write!(f, "Poisoned lock error"),
+            UnforeseenError => write!(f, "Unforeseen error"),
+            FromUtf8Error => write!(f, "From UTF8 error"),
+            HyperError => write!(f, "Hyper error"),
+            ConnectionDropped => write!(f, "Connection dropped on purpose"),
+            AddressParsingErr(ref e) => e.fmt(f),
+            StorageError(ref e) => write!(f, "Storage error: {}", e),
+            JsonEncodingError(ref e) => e.fmt(f),
+            IOError(ref e) => e.fmt(f),
+            RegexError(ref e) => e.fmt(f),
+            UrlParseError(ref e) => e.fmt(f),
+            ServerError(ref e) => e.fmt(f),
+            UpstreamError(ref e) => write!(f, "Upstream error: {}", e),
         }
     }
 }
@@ -92,29 +118,34 @@ impl Display for Error {
 impl StdErr for Error {
     fn description(&self) -> &str {
         use self::Error::*;
-        match self {
-            &PoisonedLock => "Poisoned Lock",
-            &UnforeseenError => "Unforeseen Error",
-            &FromUtf8Error => "UTF8 Conversion Error",
-            &HyperError => "Hyper Error",
-            &AddressParsingErr(ref e) => e.description(),
-            &JsonEncodingError(ref e) => e.description(),
-            &IOError(ref e) => e.description(),
-            &RegexError(ref e) => e.description(),
-            &UrlParseError(ref e) => e.description(),
-            &ServerError(ref e) => e.description(),
+        match *self {
+            PoisonedLock => "Poisoned Lock",
+            UnforeseenError => "Unforeseen Error",
+            FromUtf8Error => "UTF8 Conversion Error",
+            HyperError => "Hyper Error",
+            ConnectionDropped => "Connection Dropped",
+            AddressParsingErr(ref e) => e.description(),
+            StorageError(_) => "Storage Error",
+            JsonEncodingError(ref e) => e.description(),
+            IOError(ref e) => e.description(),
+            RegexError(ref e) => e.description(),
+            UrlParseError(ref e) => e.description(),
+            ServerError(ref e) => e.description(),
+            UpstreamError(_) => "Upstream Error",
         }
     }
 
-    fn cause(&self) -> Option<&StdErr> {
+    fn cause(&self) -> Option<&dyn StdErr> {
         use self::Error::*;
-        match self {
-            &JsonEncodingError(ref e) => Some(e),
-            &IOError(ref e) => Some(e),
-            &RegexError(ref e) => Some(e),
-            &UrlParseError(ref e) => Some(e),
-            &ServerError(ref e) => Some(e),
-            &AddressParsingErr(ref e) => Some(e),
+        match *self {
+            JsonEncodingError(ref e) => Some(e),
+            IOError(ref e) => Some(e),
+            RegexError(ref e) => Some(e),
+            UrlParseError(ref e) => Some(e),
+            ServerError(ref e) => Some(e),
+            UpstreamError(ref e) => Some(e),
+            AddressParsingErr(ref e) => Some(e),
+            StorageError(ref e) => Some(&**e),
             _ => None,
         }
     }
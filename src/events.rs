@@ -0,0 +1,130 @@
+//! Defines BinEvents, which lets whoever is interested hear about requests as they get captured
+//! by bins, instead of having to poll for them.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+
+use models::{Id, Request};
+
+/// A request that was just captured by a bin.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct BinEvent {
+    pub bin_id: Id,
+    pub request: Request,
+}
+
+/// Keeps track of subscribers to bins and publishes captured requests to them.
+///
+/// Cloning a BinEvents gives another handle on the same subscribers.
+#[derive(Clone, Default)]
+pub struct BinEvents {
+    subscribers: Arc<Mutex<Subscribers>>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    by_bin: HashMap<Id, Vec<UnboundedSender<BinEvent>>>,
+    all_bins: Vec<UnboundedSender<BinEvent>>,
+}
+
+impl BinEvents {
+    pub fn new() -> BinEvents {
+        BinEvents::default()
+    }
+
+    /// Returns a stream of requests captured by the given bin from now on. The stream ends
+    /// when the bin is closed.
+    pub fn subscribe(&self, id: &Id) -> UnboundedReceiver<BinEvent> {
+        let (tx, rx) = unbounded();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.by_bin.entry(id.to_owned()).or_default().push(tx);
+        }
+        rx
+    }
+
+    /// Returns a stream of requests captured by any bin from now on.
+    pub fn subscribe_all(&self) -> UnboundedReceiver<BinEvent> {
+        let (tx, rx) = unbounded();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.all_bins.push(tx);
+        }
+        rx
+    }
+
+    /// Sends a request captured by the given bin to all of its subscribers, forgetting those
+    /// that have gone away.
+    pub fn publish(&self, id: &Id, request: &Request) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            let event = BinEvent {
+                bin_id: id.to_owned(),
+                request: request.clone(),
+            };
+            let send = |tx: &UnboundedSender<BinEvent>| tx.unbounded_send(event.clone()).is_ok();
+            let now_empty = match subscribers.by_bin.get_mut(id) {
+                Some(senders) => {
+                    senders.retain(&send);
+                    senders.is_empty()
+                }
+                None => false,
+            };
+            if now_empty {
+                subscribers.by_bin.remove(id);
+            }
+            subscribers.all_bins.retain(&send);
+        }
+    }
+
+    /// Whether anyone is subscribed to the given bin.
+    pub fn has_subscribers(&self, id: &Id) -> bool {
+        self.subscribers
+            .lock()
+            .map(|subscribers| subscribers.by_bin.contains_key(id))
+            .unwrap_or(false)
+    }
+
+    /// Ends the streams of everyone subscribed to the given bin, e.g. because it was deleted.
+    pub fn close(&self, id: &Id) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.by_bin.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{Future, Stream};
+    use storage::test_suite::bare_request;
+
+    #[test]
+    fn test_bin_events() {
+        let events = BinEvents::new();
+        let id = Id::random();
+        let other = Id::random();
+        let rx = events.subscribe(&id);
+        let all = events.subscribe_all();
+        events.publish(&id, &bare_request("GET", "/first"));
+        events.publish(&other, &bare_request("GET", "/elsewhere"));
+        events.publish(&id, &bare_request("GET", "/second"));
+        events.close(&id);
+        events.publish(&id, &bare_request("GET", "/too-late"));
+        drop(events);
+
+        let received: Vec<String> = rx.map(|e| e.request.path).collect().wait().unwrap();
+        assert_eq!(received, vec!["/first".to_owned(), "/second".to_owned()]);
+        let received: Vec<(Id, String)> = all.map(|e| (e.bin_id, e.request.path))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            received,
+            vec![
+                (id.clone(), "/first".to_owned()),
+                (other, "/elsewhere".to_owned()),
+                (id.clone(), "/second".to_owned()),
+                (id, "/too-late".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Defines the WebSocket feed, which pushes requests to connected clients as they get captured
+//! by bins.
+//!
+//! Hyper can't hand connections over to WebSockets, so the feed listens on a port of its own.
+//! Clients connect to `/rusqbins/ws` to hear about every bin, or to `/rusqbins/${bin_id}/ws` to
+//! hear about a single bin. Every captured request is sent as a JSON text message of the form
+//! `{"bin_id": ..., "request": {...}}`.
+//!
+//! Clients can narrow down what they hear about by sending a RequestMatcher as a JSON text
+//! message, e.g. `{"method": "POST", "path": "^/orders"}`, which replaces the current filter.
+//! An initial filter can also be given with the query parameters of a RequestFilter when
+//! connecting.
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use futures::{future, stream, Future, Sink, Stream};
+
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::Handle;
+
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::Error as WsError;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::Request as HandshakeRequest;
+
+use regex::Regex;
+use serde_json;
+
+use errors::*;
+use events::BinEvents;
+use models::{Id, IdExtractor, RequestFilter, RequestMatcher};
+use server::{ErrorBody, BIN_ID_PATTERN};
+use storage::Bins;
+
+/// The paths clients can connect to the feed on. Built once when the feed starts.
+struct FeedPaths {
+    all_bins: Regex,
+    bin: Regex,
+    id_extractor: IdExtractor,
+}
+
+impl FeedPaths {
+    fn new() -> Result<FeedPaths, Error> {
+        Ok(FeedPaths {
+            all_bins: Regex::new(r"^/rusqbins/ws/?$")?,
+            bin: Regex::new(&format!(r"^/rusqbins/({})/ws/?$", BIN_ID_PATTERN))?,
+            id_extractor: IdExtractor::new(),
+        })
+    }
+}
+
+/// What a client connected to the feed wants to hear about.
+struct Subscription {
+    /// The bin to follow, or None for all bins.
+    bin_id: Option<Id>,
+    /// Which requests to pass on.
+    filter: RequestFilter,
+}
+
+impl Subscription {
+    /// Works out a Subscription from the path and query string a client connected with,
+    /// returning the HTTP status to refuse the connection with if there is a problem.
+    fn parse<T>(
+        path_and_query: &str,
+        paths: &FeedPaths,
+        bins: &Arc<Mutex<T>>,
+    ) -> Result<Subscription, u16>
+    where
+        T: Bins,
+    {
+        let mut parts = path_and_query.splitn(2, '?');
+        let path = parts.next().unwrap_or("");
+        let query = parts.next().unwrap_or("");
+
+        let bin_id = if paths.all_bins.is_match(path) {
+            None
+        } else {
+            let id = paths
+                .bin
+                .captures(path)
+                .and_then(|c| c.get(1))
+                .and_then(|m| paths.id_extractor.parse(m.as_str()))
+                .ok_or(404_u16)?;
+            let bins = bins.lock().map_err(|_| 500_u16)?;
+            match bins.get_bin_summary(&id) {
+                Ok(Some(_)) => Some(id),
+                Ok(None) => return Err(404),
+                Err(_) => return Err(500),
+            }
+        };
+
+        let filter = RequestFilter::from_query(query).map_err(|_| 400_u16)?;
+        Ok(Subscription { bin_id, filter })
+    }
+}
+
+/// Parses a filter sent by a client, returning a description of the problem if it can't be
+/// used.
+fn parse_filter(text: &str) -> Result<RequestFilter, String> {
+    let matcher: RequestMatcher = serde_json::from_str(text).map_err(|e| {
+        format!("Invalid filter: {}", e)
+    })?;
+    matcher.validate()?;
+    Ok(RequestFilter {
+        matcher,
+        ..RequestFilter::default()
+    })
+}
+
+/// Listens for feed clients on the given address, returning a Future that serves them for as
+/// long as it is polled.
+pub fn serve_feed<T>(
+    addr: &SocketAddr,
+    bins: Arc<Mutex<T>>,
+    events: BinEvents,
+    handle: &Handle,
+) -> Result<Box<dyn Future<Item = (), Error = ()>>, Error>
+where
+    T: Bins + Send + 'static,
+{
+    let listener = TcpListener::bind(addr, handle)?;
+    let paths = Arc::new(FeedPaths::new()?);
+    let handle = handle.clone();
+    Ok(Box::new(
+        listener
+            .incoming()
+            .for_each(move |(stream, _)| {
+                handle.spawn(serve_client(
+                    stream,
+                    paths.clone(),
+                    bins.clone(),
+                    events.clone(),
+                ));
+                Ok(())
+            })
+            .map_err(|e| error!("Feed error: {}", e)),
+    ))
+}
+
+/// Does the WebSocket handshake with a client, then sends it the requests it is interested in
+/// until either side goes away.
+fn serve_client<T>(
+    stream: TcpStream,
+    paths: Arc<FeedPaths>,
+    bins: Arc<Mutex<T>>,
+    events: BinEvents,
+) -> Box<dyn Future<Item = (), Error = ()>>
+where
+    T: Bins + Send + 'static,
+{
+    let subscription = Rc::new(RefCell::new(None));
+    let accepted = subscription.clone();
+    let handshake = accept_hdr_async(stream, move |req: &HandshakeRequest| {
+        let sub = Subscription::parse(&req.path, &paths, &bins).map_err(WsError::Http)?;
+        *accepted.borrow_mut() = Some(sub);
+        Ok(None)
+    });
+    Box::new(
+        handshake
+            .and_then(move |ws| {
+                let sub = match subscription.borrow_mut().take() {
+                    Some(sub) => sub,
+                    None => return future::Either::A(future::ok(())),
+                };
+                let arrivals = match sub.bin_id {
+                    Some(ref id) => events.subscribe(id),
+                    None => events.subscribe_all(),
+                };
+                let filter = Rc::new(RefCell::new(sub.filter));
+                let current_filter = filter.clone();
+                let (sink, incoming) = ws.split();
+
+                // Both streams end with a None, which ends the connection: either the client
+                // went away or the bin was deleted.
+                let outgoing = arrivals
+                    .filter(move |event| current_filter.borrow().matches(&event.request))
+                    .filter_map(|event| serde_json::to_string(&event).ok())
+                    .map(|json| Some(Message::Text(json)))
+                    .map_err(|_| WsError::ConnectionClosed(None))
+                    .chain(stream::once(Ok(None)));
+                let replies = incoming
+                    .filter_map(move |message| match message {
+                        Message::Text(text) => {
+                            match parse_filter(&text) {
+                                Ok(new_filter) => {
+                                    *filter.borrow_mut() = new_filter;
+                                    None
+                                }
+                                Err(message) => {
+                                    let body = ErrorBody {
+                                        error: "bad_request",
+                                        message,
+                                    };
+                                    serde_json::to_string(&body).ok().map(Message::Text)
+                                }
+                            }
+                        }
+                        _ => None,
+                    })
+                    .map(Some)
+                    .chain(stream::once(Ok(None)));
+
+                future::Either::B(
+                    outgoing
+                        .select(replies)
+                        .take_while(|message| Ok(message.is_some()))
+                        .filter_map(|message| message)
+                        .forward(sink)
+                        .and_then(|(_, mut sink)| future::poll_fn(move || sink.close())),
+                )
+            })
+            .map(|_| ())
+            .map_err(|e| debug!("Feed connection error: {}", e)),
+    )
+}
@@ -5,14 +5,31 @@
 //!
 //! Rusqbin's web API is the following :
 //!
-//! - POST    /rusqbins                     To create a bin and get back bin_id
+//! - POST    /rusqbins                     To create a bin and get back bin_id (optionally with a JSON body of `BinOptions`)
 //! - GET     /rusqbins                     To list bin summaries
-//! - GET     /rusqbins/${bin_id}/requests  To get detailed request information for a bin
+//! - GET     /rusqbins/${bin_id}/requests  To get detailed request information for a bin (optionally filtered, see `RequestFilter`, and paged with `limit`, `offset`, `order` and `cursor`)
+//! - DELETE  /rusqbins/${bin_id}/requests  To clear a bin's requests, keeping the bin and its config
 //! - GET     /rusqbins/${bin_id}           To get bin-specific information (lists all requests in the bin)
+//! - GET     /rusqbins/${bin_id}/requests/${request_id} To get a single request
+//! - DELETE  /rusqbins/${bin_id}/requests/${request_id} To delete a single request
+//! - GET     /rusqbins/${bin_id}/requests/wait?count=N&timeout=SECS To wait until a bin has at least N requests and get them
+//! - GET     /rusqbins/${bin_id}/stream    To get a stream of requests captured by a bin as Server-Sent Events
+//! - POST    /rusqbins/${bin_id}/verify    To check an `Expectation` against a bin's requests
+//! - POST    /rusqbins/${bin_id}/replay    To send a bin's requests again to another server (see `Replay`), getting back a `ReplayOutcome` for each
+//! - GET     /rusqbins/${bin_id}/scenarios To get the current state of each of a bin's `Scenario`s
+//! - DELETE  /rusqbins/${bin_id}/scenarios To put a bin's scenarios back in their initial states
+//! - GET     /rusqbins/${bin_id}/config    To get a bin's `BinConfig`
+//! - PUT     /rusqbins/${bin_id}/config    To replace a bin's `BinConfig` (e.g. the response sent to captured requests, or an upstream to forward them to)
 //! - DELETE  /rusqbins/${bin_id}           To delete a bin
 //!
-//! In any other case, send requests with a X-Rusqbin-Id header with a bin_id to have your requests
-//! logged to a bin for later retrieval.
+//! In any other case, send requests with a X-Rusqbin-Id header with a bin_id, or to
+//! `/${bin_id}/any/path` (see `ServerConfig::bin_path_prefix`), or with a Host of
+//! `${bin_id}.${base_domain}` (see `ServerConfig::bin_base_domain`), or with a
+//! `?rusqbin_id=${bin_id}` query parameter (see `ServerConfig::bin_query_param`), to have your
+//! requests logged to a bin for later retrieval.
+//!
+//! With `ServerConfig::websocket_port` set, captured requests are also pushed to WebSocket clients
+//! connected to `/rusqbins/ws` or `/rusqbins/${bin_id}/ws` on that port (see the `feed` module).
 //!
 //! To use it as a binary, simply install it using `cargo install rusqbin` and then `rusqbin`,
 //! and follow the simple usage instructions.
@@ -62,7 +79,7 @@
 //! // storage unlocking with braces properly
 //! let bin = {
 //!   let mut server_storage = server.storage.lock().unwrap();
-//!   server_storage.create_bin()
+//!   server_storage.create_bin().unwrap()
 //! };
 //! let bin_id = bin.id.value();
 //!
@@ -79,7 +96,7 @@
 //! // in our rusqbin server
 //! {
 //!   let mut server_storage = server.storage.lock().unwrap();
-//!   let bin_requests: &Bin = server_storage.get_bin(&bin.id).unwrap();
+//!   let bin_requests: Bin = server_storage.get_bin(&bin.id).unwrap().unwrap();
 //!   let req = &bin_requests[0];
 //!   assert_eq!(req.method, "POST".to_owned());
 //!   assert_eq!(req.path, "/hello/world".to_owned());
@@ -91,16 +108,23 @@
 //! ```
 //!
 //! In the example above, we use the default `InMemoryBins` for storage, but you can pass any given implementation of
-//! `rusqbin::storage::Bins` when creating a BinsServer.
+//! `rusqbin::storage::Bins` when creating a BinsServer, such as the file-backed `FileBins` or
+//! the SQLite-backed `SqliteBins`.
 //!
 //! [Requestbin](https://requestb.in/) written in Rust. Inspired by [Requestinator](https://github.com/DonMcNamara/requestinator)
 #[macro_use]
 extern crate hyper;
 extern crate futures;
+extern crate tokio_core;
+extern crate tokio_tungstenite;
 extern crate uuid;
 extern crate regex;
 extern crate time;
 extern crate url;
+extern crate base64;
+extern crate rand;
+#[macro_use]
+extern crate rusqlite;
 #[macro_use]
 extern crate lazy_static;
 
@@ -115,4 +139,9 @@ extern crate log;
 pub mod models;
 pub mod storage;
 pub mod server;
+pub mod events;
+pub mod scenarios;
+pub mod replay;
+pub mod templates;
+pub mod feed;
 pub mod errors;
@@ -1,35 +1,86 @@
 extern crate rusqbin;
 extern crate hyper;
 extern crate clap;
+extern crate futures;
+extern crate tokio_core;
+extern crate serde_json;
 
 #[macro_use]
 extern crate log;
 extern crate env_logger;
 extern crate openssl_probe;
 
-use rusqbin::server::BinsServer;
-use rusqbin::storage::InMemoryBins;
-use clap::{Arg, App};
+use rusqbin::models::{BinOptions, IdExtractor, Pattern, Replay, ReplayOutcome, RequestMatcher};
+use rusqbin::server::{BinsServer, ServerConfig};
+use rusqbin::storage::{Bins, InMemoryBins, FileBins, SqliteBins};
+use clap::{Arg, App, ArgMatches, SubCommand};
+use futures::{Future, Stream};
+use tokio_core::reactor::Core;
 
 use std::error::Error;
 use std::process::exit;
+use std::str::FromStr;
 
 const DEFAULT_PORT: usize = 9999;
 const DEFAULT_PORT_STR: &'static str = "9999";
 
+const DEFAULT_QUERY_PARAM: &str = "rusqbin_id";
+
+const DEFAULT_SERVER: &str = "http://localhost:9999";
+
 const GREET: &'static str = r#"
 
 **************************** Rusqbin ****************************
 
 Send:
 - POST    /rusqbins                    To create a bin and get back bin_id
+                                       (optionally with a JSON body of bin
+                                       options, e.g. {"max_requests": 100,
+                                       "ttl_secs": 3600})
 - GET     /rusqbins                    To list bin summaries
 - GET     /rusqbins/${bin_id}          To get bin-specific summary information
 - GET     /rusqbins/${bin_id}/requests To get detailed request information for a bin
+                                       (filter with e.g. ?method=POST
+                                       &path_glob=/a/*&header=X-Id&body=text
+                                       &since=MILLIS, and page with ?limit=N
+                                       &order=desc&cursor=...)
+- DELETE  /rusqbins/${bin_id}/requests To clear a bin's requests, keeping
+                                       the bin and its config
+- GET     /rusqbins/${bin_id}/requests/${request_id}
+                                       To get a single request
+- DELETE  /rusqbins/${bin_id}/requests/${request_id}
+                                       To delete a single request
+- GET     /rusqbins/${bin_id}/requests/wait?count=N&timeout=SECS
+                                       To wait for a bin to have N requests
+- GET     /rusqbins/${bin_id}/stream   To get a live stream (Server-Sent
+                                       Events) of a bin's requests
+- POST    /rusqbins/${bin_id}/verify   To check that a bin captured expected
+                                       calls, e.g. {"match": {"method": "POST"},
+                                       "count": {"exactly": 1}}
+- POST    /rusqbins/${bin_id}/replay   To send a bin's requests again, e.g.
+                                       {"target": "http://localhost:3000"}
+                                       (also: rusqbin replay ${bin_id} TARGET)
+- GET     /rusqbins/${bin_id}/config   To get a bin's config
+- PUT     /rusqbins/${bin_id}/config   To set a bin's config, e.g. the response
+                                       to send back for captured requests:
+                                       {"response": {"status": 201}} (with
+                                       "template": true, bodies and headers
+                                       can echo e.g. {{headers.X-Id}}), or an
+                                       http "upstream" to forward them to
+- GET     /rusqbins/${bin_id}/scenarios
+                                       To get the current state of a bin's
+                                       scenarios (sequenced responses)
+- DELETE  /rusqbins/${bin_id}/scenarios
+                                       To reset a bin's scenarios
 - DELETE  /rusqbins/${bin_id}          To delete a bin
 
 In any other case, send requests with a X-Rusqbin-Id header with a
-bin_id to have your requests logged to a bin for later retrieval.
+bin_id, to /${bin_id}/any/path, or with a ?rusqbin_id=${bin_id} query
+parameter, to have your requests logged to a bin for later retrieval.
+With --base-domain, requests to ${bin_id}.${base_domain} are logged too.
+
+With --ws-port, connect a WebSocket to /rusqbins/ws (all bins) or
+/rusqbins/${bin_id}/ws on that port to get requests as they arrive.
 "#;
 
 fn main() {
@@ -42,7 +93,7 @@ fn main() {
     }
 }
 
-fn inner_main() -> Result<(), Box<Error>> {
+fn inner_main() -> Result<(), Box<dyn Error>> {
 
     openssl_probe::init_ssl_cert_env_vars();
     env_logger::init()?;
@@ -59,27 +110,285 @@ fn inner_main() -> Result<(), Box<Error>> {
                 .required(false)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("data-dir")
+                .short("d")
+                .long("data-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .help(
+                    "Persists bins to an append-only log in this directory instead of keeping them in memory",
+                )
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("sqlite")
+                .short("s")
+                .long("sqlite")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Stores bins in this SQLite database file instead of keeping them in memory")
+                .conflicts_with("data-dir")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("max-requests")
+                .long("max-requests")
+                .takes_value(true)
+                .value_name("COUNT")
+                .help(
+                    "Default maximum number of requests kept per bin; the oldest are dropped beyond it",
+                )
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("ttl")
+                .long("ttl")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .help("Default number of seconds after which bins are deleted")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("path-prefix")
+                .long("path-prefix")
+                .takes_value(true)
+                .value_name("PREFIX")
+                .help("Requests to PREFIX/{bin_id}/... are captured by that bin (default: none)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("base-domain")
+                .long("base-domain")
+                .takes_value(true)
+                .value_name("DOMAIN")
+                .help("Requests to {bin_id}.DOMAIN are captured by that bin")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("query-param")
+                .long("query-param")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Requests with ?NAME={bin_id} are captured by that bin (default: rusqbin_id)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("ws-port")
+                .long("ws-port")
+                .takes_value(true)
+                .value_name("PORT")
+                .help("Serves a WebSocket feed of captured requests on this port")
+                .required(false),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about(
+                    "Asks a running server to send a bin's requests again to TARGET, oldest first",
+                )
+                .arg(
+                    Arg::with_name("bin")
+                        .help("Id of the bin whose requests to replay")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("target")
+                        .help("Base URL to send the requests to, e.g. http://localhost:3000")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("server")
+                        .long("server")
+                        .takes_value(true)
+                        .value_name("URL")
+                        .default_value(DEFAULT_SERVER)
+                        .help("Base URL of the rusqbin server holding the bin"),
+                )
+                .arg(
+                    Arg::with_name("request")
+                        .long("request")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("REQUEST_ID")
+                        .help("Only replays this request (can be given more than once)"),
+                )
+                .arg(
+                    Arg::with_name("method")
+                        .long("method")
+                        .takes_value(true)
+                        .value_name("METHOD")
+                        .help("Only replays requests with this method"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .value_name("REGEX")
+                        .help("Only replays requests whose path matches this regex"),
+                ),
+        )
         .get_matches();
 
-    match matches.value_of("port") {
-        Some(port_str) => {
-            let port: usize = port_str.parse().expect("Port must be number");
-            start_on_port(port)
-        }
+    if let Some(replay_matches) = matches.subcommand_matches("replay") {
+        return replay(replay_matches);
+    }
+
+    let port = match matches.value_of("port") {
+        Some(port_str) => port_str.parse().expect("Port must be number"),
         None => {
             info!("\nUsing default port {}", DEFAULT_PORT_STR);
-            start_on_port(DEFAULT_PORT)
+            DEFAULT_PORT
+        }
+    };
+
+    // Defaults are checked the same way as options sent to POST /rusqbins
+    let default_options = BinOptions {
+        max_requests: parse_arg(&matches, "max-requests")?,
+        ttl_secs: parse_arg(&matches, "ttl")?,
+    };
+    default_options.validate().map_err(
+        |e| format!("Invalid default bin options: {}", e),
+    )?;
+
+    let config = ServerConfig {
+        default_max_requests: default_options.max_requests,
+        default_ttl_secs: default_options.ttl_secs,
+        bin_path_prefix: matches.value_of("path-prefix").unwrap_or("").to_owned(),
+        bin_base_domain: matches.value_of("base-domain").map(|d| d.to_owned()),
+        bin_query_param: Some(
+            matches
+                .value_of("query-param")
+                .unwrap_or(DEFAULT_QUERY_PARAM)
+                .to_owned(),
+        ),
+        websocket_port: matches.value_of("ws-port").map(|port| {
+            port.parse().expect("WebSocket port must be a number")
+        }),
+        ..ServerConfig::default()
+    };
+
+    match (matches.value_of("data-dir"), matches.value_of("sqlite")) {
+        (Some(dir), _) => {
+            let bins = FileBins::open(dir)?;
+            info!("Using bins log at {}", bins.path().display());
+            start_on_port(port, bins, config)
+        }
+        (_, Some(file)) => {
+            info!("Using SQLite database at {}", file);
+            start_on_port(port, SqliteBins::open(file)?, config)
+        }
+        _ => start_on_port(port, InMemoryBins::new(), config),
+    }
+}
+
+/// Parses the value of the given argument, if it was passed.
+fn parse_arg<T>(matches: &ArgMatches, name: &str) -> Result<Option<T>, String>
+where
+    T: FromStr,
+{
+    match matches.value_of(name) {
+        Some(value) => {
+            value.parse().map(Some).map_err(|_| {
+                format!("Invalid --{}, must be a number: {}", name, value)
+            })
         }
+        None => Ok(None),
     }
 }
 
-/// Starts a BinsServer on the given port with an InMemory database.
-fn start_on_port(p: usize) -> Result<(), Box<Error>> {
-    let s = BinsServer::new(p, InMemoryBins::new());
+/// Starts a BinsServer on the given port with the given database and settings.
+fn start_on_port<T>(p: usize, bins: T, config: ServerConfig) -> Result<(), Box<dyn Error>>
+where
+    T: Bins + Send + 'static,
+{
+    let s = BinsServer::with_config(p, bins, config);
     println!("{}\n\n Server starting on {}", GREET, s.address);
     Ok(s.run()?)
 }
 
+/// Asks a running server to replay a bin's requests, printing how each one went.
+fn replay(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let id_extractor = IdExtractor::new();
+    let request_ids = matches
+        .values_of("request")
+        .into_iter()
+        .flatten()
+        .map(|id| {
+            id_extractor.parse(id).ok_or_else(
+                || format!("Invalid request id: {}", id),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let replay = Replay {
+        target: matches.value_of("target").unwrap_or("").to_owned(),
+        request_ids,
+        matcher: RequestMatcher {
+            method: matches.value_of("method").map(|m| m.to_owned()),
+            path: matches.value_of("path").map(Pattern::from),
+            ..RequestMatcher::default()
+        },
+    };
+    replay.validate()?;
+
+    let uri = format!(
+        "{}/rusqbins/{}/replay",
+        matches.value_of("server").unwrap_or(DEFAULT_SERVER).trim_end_matches('/'),
+        matches.value_of("bin").unwrap_or("")
+    ).parse()?;
+    let mut req = hyper::client::Request::new(hyper::Method::Post, uri);
+    req.set_body(serde_json::to_string(&replay)?);
+    let mut core = Core::new()?;
+    let client = hyper::Client::new(&core.handle());
+    let (status, body) = core.run(client.request(req).and_then(|res| {
+        let status = res.status();
+        res.body().concat2().map(move |body| (status, body.to_vec()))
+    }))?;
+    if !status.is_success() {
+        return Err(
+            format!(
+                "Server responded with {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            ).into(),
+        );
+    }
+
+    let outcomes: Vec<ReplayOutcome> = serde_json::from_slice(&body)?;
+    for outcome in &outcomes {
+        match (outcome.status, &outcome.error) {
+            (Some(status), _) => {
+                println!(
+                    "{} {} {} -> {} ({}ms)",
+                    outcome.request_id,
+                    outcome.method,
+                    outcome.url,
+                    status,
+                    outcome.latency_ms
+                )
+            }
+            (None, error) => {
+                println!(
+                    "{} {} {} -> failed: {}",
+                    outcome.request_id,
+                    outcome.method,
+                    outcome.url,
+                    error.as_ref().map(|e| &e[..]).unwrap_or("unknown error")
+                )
+            }
+        }
+    }
+    let failed = outcomes.iter().filter(|o| o.status.is_none()).count();
+    println!("Replayed {} request(s), {} failed", outcomes.len(), failed);
+    if failed > 0 {
+        Err(format!("{} request(s) could not be replayed", failed).into())
+    } else {
+        Ok(())
+    }
+}
+
 fn version() -> String {
     let (maj, min, pat) = (
         option_env!("CARGO_PKG_VERSION_MAJOR"),
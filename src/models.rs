@@ -17,8 +17,17 @@ use std::collections::HashMap;
 
 use std::fmt;
 use uuid::Uuid;
+use time;
 
-use regex::Regex;
+use regex::{self, Regex};
+
+use base64;
+use url::{form_urlencoded, Url};
+use serde_json::{self, Value};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use templates;
 
 const ID_REGEXP: &'static str =
     r"^((?i)[A-F0-9]{8}\-[A-F0-9]{4}\-4[A-F0-9]{3}\-[89AB][A-F0-9]{3}\-[A-F0-9]{12})$";
@@ -96,30 +105,1156 @@ impl fmt::Display for Id {
     }
 }
 
+/// The Id of requests recorded before they had Ids.
+fn missing_id() -> Id {
+    Id(String::new())
+}
+
 /// A record of an HTTP request made to the server.
-#[derive(PartialEq, Debug, Eq, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
 pub struct Request {
+    /// Uniquely identifies the request. Requests recorded before they had Ids are given one
+    /// by storage when loaded; until then, this is empty.
+    #[serde(default = "missing_id")]
+    pub id: Id,
+    /// Position of the request among all those ever captured by its bin, starting at 1. Filled
+    /// in by storage when the request is inserted.
+    #[serde(default)]
+    pub seq: usize,
     pub content_length: Option<u64>,
     pub content_type: Option<String>,
     pub time: i64,
     pub method: String,
     pub path: String,
-    pub body: Option<String>,
+    pub body: Option<Body>,
     pub headers: HashMap<String, Vec<String>>,
     pub query_string: HashMap<String, Vec<String>>,
+    /// The response that was sent back for the request, once it has been sent.
+    #[serde(default)]
+    pub response: Option<RecordedResponse>,
+}
+
+/// A record of an HTTP response sent back for a captured request, be it a mock, relayed from
+/// an upstream or injected as a fault.
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: HashMap<String, Vec<String>>,
+    pub body: Option<Body>,
+    /// Milliseconds between the request being received and the response starting to be
+    /// sent, including any injected delay.
+    #[serde(default)]
+    pub latency_ms: u64,
+}
+
+/// The raw bytes of a request body.
+///
+/// JSON encodes to an object holding the bytes as base64, along with a marker for that
+/// encoding and, when the bytes are valid UTF-8, a `text` view of them:
+///
+/// ```
+/// # extern crate rusqbin;
+/// # extern crate serde_json;
+/// # use rusqbin::models::*;
+/// # fn main() {
+/// let body = Body::from("hey");
+/// assert_eq!(body.text(), Some("hey"));
+///
+/// let encoded = serde_json::to_string(&body).unwrap();
+/// assert_eq!(encoded, r#"{"encoding":"base64","data":"aGV5","text":"hey"}"#);
+///
+/// let binary = Body::new(vec![0xff, 0xfe]);
+/// assert_eq!(binary.text(), None);
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Eq, Clone)]
+pub struct Body(Vec<u8>);
+
+/// The only encoding used for body data in JSON.
+const BODY_ENCODING: &str = "base64";
+
+impl Body {
+    /// Wraps raw body bytes.
+    pub fn new(bytes: Vec<u8>) -> Body {
+        Body(bytes)
+    }
+
+    /// Gets the raw bytes out of a Body.
+    pub fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Gets the body as text, if it is valid UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        ::std::str::from_utf8(&self.0).ok()
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Body {
+        Body(s.into_bytes())
+    }
+}
+
+impl<'a> From<&'a str> for Body {
+    fn from(s: &'a str) -> Body {
+        Body(s.as_bytes().to_vec())
+    }
+}
+
+impl Serialize for Body {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let text = self.text();
+        let len = if text.is_some() { 3 } else { 2 };
+        let mut state = serializer.serialize_struct("Body", len)?;
+        state.serialize_field("encoding", BODY_ENCODING)?;
+        state.serialize_field("data", &base64::encode(&self.0))?;
+        if let Some(text) = text {
+            state.serialize_field("text", text)?;
+        }
+        state.end()
+    }
+}
+
+/// What a Body can be decoded from. Plain strings are what bodies were stored as before they
+/// were kept as bytes, so they are still accepted.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BodyRepr {
+    Encoded { encoding: String, data: String },
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Body, D::Error> {
+        match BodyRepr::deserialize(deserializer)? {
+            BodyRepr::Encoded { ref encoding, ref data } if encoding == BODY_ENCODING => {
+                base64::decode(data).map(Body).map_err(de::Error::custom)
+            }
+            BodyRepr::Encoded { encoding, .. } => Err(de::Error::custom(format!(
+                "unknown body encoding: {}",
+                encoding
+            ))),
+            BodyRepr::Text(text) => Ok(Body::from(text)),
+        }
+    }
+}
+
+/// Options for creating a Bin of requests.
+///
+/// Every field is optional when decoding from JSON.
+#[derive(PartialEq, Debug, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BinOptions {
+    /// Maximum number of requests to keep in the bin. Once reached, the oldest request is
+    /// dropped to make room for each new one. No limit if None.
+    pub max_requests: Option<usize>,
+    /// Number of seconds after creation at which the bin and its requests are deleted.
+    /// Kept forever if None.
+    pub ttl_secs: Option<u64>,
+}
+
+/// Longest TTL, in seconds, that a bin can be given: about 10 years.
+pub const MAX_TTL_SECS: u64 = 10 * 365 * 24 * 60 * 60;
+
+impl BinOptions {
+    /// Checks that a bin can be created with these options, returning a description of the
+    /// problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_requests == Some(0) {
+            return Err("max_requests must be greater than 0".to_owned());
+        }
+        match self.ttl_secs {
+            Some(0) => Err("ttl_secs must be greater than 0".to_owned()),
+            Some(ttl) if ttl > MAX_TTL_SECS => {
+                Err(format!("ttl_secs must be at most {}: {}", MAX_TTL_SECS, ttl))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// When a bin created at the given time (in milliseconds since the Unix epoch) with these
+    /// options expires, if ever.
+    pub fn expires_at(&self, created_at: i64) -> Option<i64> {
+        self.ttl_secs.map(|ttl| {
+            ttl.checked_mul(1000)
+                .filter(|&ttl_ms| ttl_ms <= i64::MAX as u64)
+                .and_then(|ttl_ms| created_at.checked_add(ttl_ms as i64))
+                .unwrap_or(i64::MAX)
+        })
+    }
+}
+
+/// Per-bin settings that can be changed at any time after the bin has been created.
+///
+/// Every field is optional when decoding from JSON.
+#[derive(PartialEq, Debug, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BinConfig {
+    /// What to answer requests captured by the bin with when none of the `rules` match. An
+    /// empty 200 if None.
+    pub response: Option<MockResponse>,
+    /// Rules for choosing a response based on the captured request, tried in order.
+    pub rules: Vec<ResponseRule>,
+    /// Sequences of responses for requests that match, tried in order before the `rules`.
+    pub scenarios: Vec<Scenario>,
+    /// Base URL of a plain HTTP server to forward captured requests to, e.g.
+    /// `http://localhost:8080/api`, when none of the `scenarios` or `rules` match. Its
+    /// responses are relayed back, and recorded along with the requests. Takes the place of
+    /// the default `response`.
+    pub upstream: Option<String>,
+    /// Misbehaviour to inject when answering captured requests.
+    pub faults: Faults,
+}
+
+impl BinConfig {
+    /// Picks the response for a captured request: that of the first rule matching it,
+    /// falling back to the default `response` unless the bin forwards to an upstream.
+    pub fn response_for(&self, request: &Request) -> Option<&MockResponse> {
+        let rule_response = self.rules
+            .iter()
+            .find(|rule| rule.matcher.matches(request))
+            .map(|rule| &rule.response);
+        if self.upstream.is_some() {
+            rule_response
+        } else {
+            rule_response.or(self.response.as_ref())
+        }
+    }
+
+    /// Checks that the config can be used, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref response) = self.response {
+            response.validate()?;
+        }
+        if let Some(ref upstream) = self.upstream {
+            validate_base_url("upstream", upstream)?;
+        }
+        self.faults.validate()?;
+        for (idx, rule) in self.rules.iter().enumerate() {
+            rule.matcher
+                .validate()
+                .and_then(|_| rule.response.validate())
+                .map_err(|e| format!("Rule {}: {}", idx, e))?;
+        }
+        for (idx, scenario) in self.scenarios.iter().enumerate() {
+            if self.scenarios[..idx].iter().any(|s| s.name == scenario.name) {
+                return Err(format!("Duplicate scenario name: {}", scenario.name));
+            }
+            scenario.validate().map_err(|e| {
+                format!("Scenario {}: {}", scenario.name, e)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a URL can have requests sent to paths under it.
+fn validate_base_url(what: &str, base_url: &str) -> Result<(), String> {
+    let url = Url::parse(base_url).map_err(|e| format!("Invalid {}: {}", what, e))?;
+    if url.scheme() != "http" || !url.has_host() {
+        return Err(format!(
+            "Invalid {}, only http://host[:port][/path] URLs are supported: {}",
+            what,
+            base_url
+        ));
+    }
+    Ok(())
+}
+
+/// A state machine for answering successive matching requests differently, e.g. failing the
+/// first couple of calls and then succeeding, to exercise clients' retry logic.
+///
+/// Each request that matches gets the response of the scenario's current state, then moves
+/// the scenario on to that state's `next` state (or leaves it where it is if there is none).
+///
+/// ```
+/// # extern crate rusqbin;
+/// # extern crate serde_json;
+/// # use rusqbin::models::*;
+/// # fn main() {
+/// let scenario: Scenario = serde_json::from_str(r#"{
+///   "name": "flaky-payments",
+///   "match": { "path": "^/payments$" },
+///   "initial_state": "down",
+///   "states": {
+///     "down": { "response": { "status": 503 }, "next": "still-down" },
+///     "still-down": { "response": { "status": 503 }, "next": "up" },
+///     "up": { "response": { "status": 200 } }
+///   }
+/// }"#).unwrap();
+/// assert!(scenario.validate().is_ok());
+/// let (response, next) = scenario.step("still-down").unwrap();
+/// assert_eq!((response.status, next), (503, "up"));
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    /// Which captured requests drive the scenario. All of them if empty.
+    #[serde(rename = "match", default)]
+    pub matcher: RequestMatcher,
+    /// The state the scenario starts in, and goes back to when reset.
+    pub initial_state: String,
+    pub states: HashMap<String, ScenarioStep>,
+}
+
+/// What a Scenario does in a given state.
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+pub struct ScenarioStep {
+    pub response: MockResponse,
+    /// The state to move on to once the response has been sent.
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+impl Scenario {
+    /// Checks that the scenario can be used, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        self.matcher.validate()?;
+        if !self.states.contains_key(&self.initial_state) {
+            return Err(format!("Unknown initial state: {}", self.initial_state));
+        }
+        for (state, step) in sorted(&self.states) {
+            step.response.validate().map_err(
+                |e| format!("State {}: {}", state, e),
+            )?;
+            if let Some(ref next) = step.next {
+                if !self.states.contains_key(next) {
+                    return Err(format!("State {}: unknown next state: {}", state, next));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the response to send in the given state, along with the state to move on to.
+    /// None if there is no such state.
+    pub fn step(&self, state: &str) -> Option<(&MockResponse, &str)> {
+        self.states.get_key_value(state).map(|(state, step)| {
+            (&step.response, step.next.as_ref().unwrap_or(state).as_str())
+        })
+    }
+}
+
+/// Latency and faults to inject when answering requests captured by a bin, for exercising
+/// clients' timeout and retry logic.
+///
+/// Every field is optional when decoding from JSON, and by default nothing is injected.
+///
+/// Requests are captured even when a fault is injected.
+#[derive(PartialEq, Debug, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Faults {
+    /// Fixed number of milliseconds to wait before responding.
+    pub delay_ms: u64,
+    /// Upper bound of a random number of milliseconds to wait on top of `delay_ms`.
+    pub jitter_ms: u64,
+    /// Percentage (0 - 100) of requests for which the connection is dropped instead of
+    /// responding.
+    pub drop_percent: u8,
+    /// Percentage (0 - 100) of requests that get an empty `error_status` response instead of
+    /// the usual one.
+    pub error_percent: u8,
+    /// Status to respond with when injecting an error. 500 if None.
+    pub error_status: Option<u16>,
+    /// If set, response bodies are sent in chunks with a pause between each.
+    pub slow_body: Option<SlowBody>,
+}
+
+/// How to slowly stream a response body.
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+pub struct SlowBody {
+    /// Number of bytes in each chunk.
+    pub chunk_size: usize,
+    /// Milliseconds to wait before sending each chunk.
+    pub chunk_delay_ms: u64,
+}
+
+/// Longest delay, in milliseconds, that any single fault can inject.
+pub const MAX_FAULT_DELAY_MS: u64 = 600_000;
+
+impl Faults {
+    /// Checks that the faults can be injected, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        let delays = [
+            ("delay_ms", Some(self.delay_ms)),
+            ("jitter_ms", Some(self.jitter_ms)),
+            (
+                "slow_body chunk_delay_ms",
+                self.slow_body.as_ref().map(|s| s.chunk_delay_ms),
+            ),
+        ];
+        for &(name, delay) in &delays {
+            if let Some(delay) = delay.filter(|&d| d > MAX_FAULT_DELAY_MS) {
+                return Err(format!(
+                    "Invalid {}, must be at most {}: {}",
+                    name,
+                    MAX_FAULT_DELAY_MS,
+                    delay
+                ));
+            }
+        }
+        if self.drop_percent > 100 {
+            return Err(format!("Invalid drop_percent: {}", self.drop_percent));
+        }
+        if self.error_percent > 100 {
+            return Err(format!("Invalid error_percent: {}", self.error_percent));
+        }
+        if let Some(status) = self.error_status {
+            validate_status(status)?;
+        }
+        match self.slow_body {
+            Some(SlowBody { chunk_size: 0, .. }) => {
+                Err("slow_body chunk_size must be greater than 0".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A response to send when a captured request matches.
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+pub struct ResponseRule {
+    #[serde(rename = "match")]
+    pub matcher: RequestMatcher,
+    pub response: MockResponse,
+}
+
+/// A regex as written in a config or query, compiled once up front so that it isn't compiled
+/// again for every request it gets matched against.
+///
+/// Compares, and encodes to JSON, as the text it was written as. That text doesn't have to be
+/// a valid regex: nothing matches it then, and `validate` says why.
+///
+/// ```
+/// # use rusqbin::models::*;
+/// let pattern = Pattern::from("^/orders/[0-9]+$");
+/// assert!(pattern.is_match("/orders/42"));
+/// assert!(Pattern::from("(").validate().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    regex: Result<Regex, String>,
+}
+
+impl Pattern {
+    pub fn new<S: Into<String>>(source: S) -> Pattern {
+        let source = source.into();
+        let regex = Regex::new(&source).map_err(|e| e.to_string());
+        Pattern { source, regex }
+    }
+
+    /// A pattern for paths written as a glob, where `*` stands for anything but `/`, `**` for
+    /// anything at all and `?` for any single character but `/`.
+    pub fn glob<S: Into<String>>(glob: S) -> Pattern {
+        let source = glob.into();
+        let regex = Regex::new(&glob_regex(&source)).map_err(|e| e.to_string());
+        Pattern { source, regex }
+    }
+
+    /// The text the pattern was written as.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Checks that the pattern compiled, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        self.regex.as_ref().map(|_| ()).map_err(|e| e.to_owned())
+    }
+
+    /// Whether the pattern matches the given text. Never true if the pattern is invalid.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.as_ref().is_ok_and(|re| re.is_match(text))
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Pattern {}
+
+impl<'a> From<&'a str> for Pattern {
+    fn from(source: &'a str) -> Pattern {
+        Pattern::new(source)
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(source: String) -> Pattern {
+        Pattern::new(source)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D>(deserializer: D) -> Result<Pattern, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(Pattern::new)
+    }
+}
+
+/// Criteria for matching captured requests. A request matches if it meets all of the given
+/// criteria, so an empty RequestMatcher matches everything.
+///
+/// Every field is optional when decoding from JSON.
+///
+/// ```
+/// # extern crate rusqbin;
+/// # extern crate serde_json;
+/// # use rusqbin::models::*;
+/// # fn main() {
+/// let matcher: RequestMatcher = serde_json::from_str(r#"{
+///   "method": "POST",
+///   "path": "^/orders/[0-9]+$",
+///   "headers": { "X-Tenant": "acme" },
+///   "json": { "/status": "paid" }
+/// }"#).unwrap();
+/// assert!(matcher.validate().is_ok());
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestMatcher {
+    /// HTTP method, compared case-insensitively.
+    pub method: Option<String>,
+    /// Regex that the path, without its query string, must match.
+    pub path: Option<Pattern>,
+    /// Query parameters that must be present with the given value.
+    pub query: HashMap<String, String>,
+    /// Headers that must be present with the given value. Names are compared
+    /// case-insensitively.
+    pub headers: HashMap<String, String>,
+    /// Fields of a JSON body, as JSON Pointers (e.g. `/order/id`), and the values they must
+    /// have.
+    pub json: HashMap<String, Value>,
+}
+
+impl RequestMatcher {
+    /// Checks that the matcher can be used, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref path) = self.path {
+            path.validate().map_err(
+                |e| format!("Invalid path regex: {}", e),
+            )?;
+        }
+        for pointer in self.json.keys() {
+            if !pointer.is_empty() && !pointer.starts_with('/') {
+                return Err(format!("Invalid JSON pointer: {}", pointer));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the given request meets all of this matcher's criteria.
+    pub fn matches(&self, request: &Request) -> bool {
+        self.method.as_ref().is_none_or(|m| {
+            m.eq_ignore_ascii_case(&request.method)
+        }) && self.matches_path(request) &&
+            self.query.iter().all(|(k, v)| {
+                request.query_string.get(k).is_some_and(|vs| vs.contains(v))
+            }) &&
+            self.headers.iter().all(|(k, v)| {
+                request.headers.iter().any(|(name, values)| {
+                    name.eq_ignore_ascii_case(k) && values.contains(v)
+                })
+            }) && self.matches_json(request)
+    }
+
+    /// Lists the criteria that the given request doesn't meet, along with what it had instead.
+    /// Empty if the request matches.
+    pub fn mismatches(&self, request: &Request) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        if let Some(ref method) = self.method {
+            if !method.eq_ignore_ascii_case(&request.method) {
+                mismatches.push(Mismatch {
+                    field: "method",
+                    name: None,
+                    expected: Value::from(method.as_str()),
+                    actual: Some(Value::from(request.method.as_str())),
+                });
+            }
+        }
+        if let Some(ref pattern) = self.path {
+            if !self.matches_path(request) {
+                let path = request.path.split('?').next().unwrap_or("");
+                mismatches.push(Mismatch {
+                    field: "path",
+                    name: None,
+                    expected: Value::from(pattern.as_str()),
+                    actual: Some(Value::from(path)),
+                });
+            }
+        }
+        for (k, v) in sorted(&self.query) {
+            let actual = request.query_string.get(k);
+            if !actual.is_some_and(|vs| vs.contains(v)) {
+                mismatches.push(Mismatch {
+                    field: "query",
+                    name: Some(k.to_owned()),
+                    expected: Value::from(v.as_str()),
+                    actual: actual.map(|vs| Value::from(vs.clone())),
+                });
+            }
+        }
+        for (k, v) in sorted(&self.headers) {
+            let actual: Vec<String> = request
+                .headers
+                .iter()
+                .filter(|&(name, _)| name.eq_ignore_ascii_case(k))
+                .flat_map(|(_, values)| values.iter().cloned())
+                .collect();
+            if !actual.contains(v) {
+                mismatches.push(Mismatch {
+                    field: "headers",
+                    name: Some(k.to_owned()),
+                    expected: Value::from(v.as_str()),
+                    actual: if actual.is_empty() {
+                        None
+                    } else {
+                        Some(Value::from(actual))
+                    },
+                });
+            }
+        }
+        if !self.json.is_empty() {
+            let body: Option<Value> = request.body.as_ref().and_then(|b| {
+                serde_json::from_slice(b.bytes()).ok()
+            });
+            for (pointer, expected) in sorted(&self.json) {
+                let actual = body.as_ref().and_then(|b| b.pointer(pointer));
+                if actual != Some(expected) {
+                    mismatches.push(Mismatch {
+                        field: "json",
+                        name: Some(pointer.to_owned()),
+                        expected: expected.clone(),
+                        actual: actual.cloned(),
+                    });
+                }
+            }
+        }
+        mismatches
+    }
+
+    fn matches_path(&self, request: &Request) -> bool {
+        match self.path {
+            Some(ref pattern) => pattern.is_match(request.path.split('?').next().unwrap_or("")),
+            None => true,
+        }
+    }
+
+    fn matches_json(&self, request: &Request) -> bool {
+        if self.json.is_empty() {
+            return true;
+        }
+        let body: Value = match request.body.as_ref().map(|b| serde_json::from_slice(b.bytes())) {
+            Some(Ok(body)) => body,
+            _ => return false,
+        };
+        self.json.iter().all(|(pointer, expected)| {
+            body.pointer(pointer) == Some(expected)
+        })
+    }
+}
+
+/// Criteria for searching through captured requests, e.g. when listing a bin's requests. On
+/// top of a RequestMatcher, it can look for headers whatever their value, text in bodies and
+/// times of capture.
+///
+/// Usually built from a query string:
+///
+/// ```
+/// # use rusqbin::models::*;
+/// let filter = RequestFilter::from_query(
+///     "method=POST&path_glob=/orders/*&header=X-Tenant&body=paid&since=1500000000000",
+/// ).unwrap();
+/// assert_eq!(filter.matcher.method, Some("POST".to_owned()));
+/// assert_eq!(filter.has_headers, vec!["X-Tenant".to_owned()]);
+/// ```
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct RequestFilter {
+    pub matcher: RequestMatcher,
+    /// Glob that the path, without its query string, must match (see `Pattern::glob`).
+    pub path_glob: Option<Pattern>,
+    /// Headers that must be present, whatever their value. Names are compared
+    /// case-insensitively.
+    pub has_headers: Vec<String>,
+    /// Query parameters that must be present, whatever their value.
+    pub has_query: Vec<String>,
+    /// Text that the body must contain.
+    pub body_contains: Option<String>,
+    /// Only requests captured at or after this time, in milliseconds since the Unix epoch.
+    pub since: Option<i64>,
+    /// Only requests captured before this time, in milliseconds since the Unix epoch.
+    pub until: Option<i64>,
+}
+
+impl RequestFilter {
+    /// Builds a RequestFilter from query parameters, ignoring those it doesn't know about:
+    ///
+    /// - `method=POST`
+    /// - `path=REGEX` or `path_glob=GLOB`
+    /// - `header=NAME` for presence, or `header=NAME:VALUE`
+    /// - `query=NAME` for presence, or `query=NAME:VALUE`
+    /// - `body=TEXT`
+    /// - `json=POINTER:VALUE`, where VALUE is parsed as JSON if possible, e.g. `json=/id:42`
+    /// - `since=MILLIS` and `until=MILLIS`
+    ///
+    /// `header`, `query` and `json` may be repeated. Returns a description of the problem if
+    /// the filter can't be used.
+    pub fn from_query(query: &str) -> Result<RequestFilter, String> {
+        let mut filter = RequestFilter::default();
+        for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+            match &*k {
+                "method" => filter.matcher.method = Some(v.into_owned()),
+                "path" => filter.matcher.path = Some(Pattern::new(v.into_owned())),
+                "path_glob" => filter.path_glob = Some(Pattern::glob(v.into_owned())),
+                "header" => {
+                    match split_pair(&v) {
+                        Some((name, value)) => {
+                            filter.matcher.headers.insert(name, value);
+                        }
+                        None => filter.has_headers.push(v.into_owned()),
+                    }
+                }
+                "query" => {
+                    match split_pair(&v) {
+                        Some((name, value)) => {
+                            filter.matcher.query.insert(name, value);
+                        }
+                        None => filter.has_query.push(v.into_owned()),
+                    }
+                }
+                "json" => {
+                    let (pointer, value) = split_pair(&v).ok_or_else(|| {
+                        format!("Invalid json filter, expected POINTER:VALUE: {}", v)
+                    })?;
+                    let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+                    filter.matcher.json.insert(pointer, value);
+                }
+                "body" => filter.body_contains = Some(v.into_owned()),
+                "since" => {
+                    filter.since = Some(v.parse().map_err(|_| format!("Invalid since: {}", v))?)
+                }
+                "until" => {
+                    filter.until = Some(v.parse().map_err(|_| format!("Invalid until: {}", v))?)
+                }
+                _ => (),
+            }
+        }
+        filter.validate()?;
+        Ok(filter)
+    }
+
+    /// Checks that the filter can be used, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        self.matcher.validate()?;
+        if let Some(ref glob) = self.path_glob {
+            glob.validate().map_err(
+                |e| format!("Invalid path glob: {}", e),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Whether the given request meets all of this filter's criteria.
+    pub fn matches(&self, request: &Request) -> bool {
+        self.matcher.matches(request) && self.matches_path_glob(request) &&
+            self.has_headers.iter().all(|k| {
+                request.headers.keys().any(|name| name.eq_ignore_ascii_case(k))
+            }) &&
+            self.has_query.iter().all(
+                |k| request.query_string.contains_key(k),
+            ) &&
+            self.body_contains.as_ref().is_none_or(|text| {
+                request.body.as_ref().is_some_and(|body| {
+                    contains_bytes(body.bytes(), text.as_bytes())
+                })
+            }) && self.since.is_none_or(|since| request.time >= since) &&
+            self.until.is_none_or(|until| request.time < until)
+    }
+
+    fn matches_path_glob(&self, request: &Request) -> bool {
+        match self.path_glob {
+            Some(ref glob) => glob.is_match(request.path.split('?').next().unwrap_or("")),
+            None => true,
+        }
+    }
+}
+
+/// Splits `NAME:VALUE` into its parts.
+fn split_pair(s: &str) -> Option<(String, String)> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) => Some((name.to_owned(), value.to_owned())),
+        _ => None,
+    }
+}
+
+/// Turns a path glob into an anchored regex.
+fn glob_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    re
+}
+
+fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Entries of a map, sorted by key so that they come out in the same order every time.
+fn sorted<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<(&String, &V)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+/// A criterion of a RequestMatcher that a request didn't meet.
+#[derive(PartialEq, Debug, Eq, Clone, Serialize)]
+pub struct Mismatch {
+    /// Which of the RequestMatcher's fields it was: `method`, `path`, `query`, `headers` or
+    /// `json`.
+    pub field: &'static str,
+    /// The query parameter, header or JSON Pointer, for fields that have them.
+    pub name: Option<String>,
+    pub expected: Value,
+    /// What the request had instead, if anything.
+    pub actual: Option<Value>,
+}
+
+/// How many of a bin's requests an Expectation wants to match.
+///
+/// JSON encodes to one of `{"exactly": N}`, `{"at_least": N}` or `{"at_most": N}`.
+#[derive(PartialEq, Debug, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpectedCount {
+    Exactly(usize),
+    AtLeast(usize),
+    AtMost(usize),
+}
+
+impl Default for ExpectedCount {
+    /// At least one.
+    fn default() -> ExpectedCount {
+        ExpectedCount::AtLeast(1)
+    }
+}
+
+impl ExpectedCount {
+    /// Whether the given number of matching requests is what was expected.
+    pub fn allows(&self, count: usize) -> bool {
+        match *self {
+            ExpectedCount::Exactly(n) => count == n,
+            ExpectedCount::AtLeast(n) => count >= n,
+            ExpectedCount::AtMost(n) => count <= n,
+        }
+    }
+}
+
+impl fmt::Display for ExpectedCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExpectedCount::Exactly(n) => write!(f, "exactly {}", n),
+            ExpectedCount::AtLeast(n) => write!(f, "at least {}", n),
+            ExpectedCount::AtMost(n) => write!(f, "at most {}", n),
+        }
+    }
+}
+
+/// Maximum number of near misses reported when an Expectation isn't met.
+pub const MAX_NEAR_MISSES: usize = 3;
+
+/// A call that a bin is expected to have captured, for checking that a service under test made
+/// the requests it should have.
+///
+/// Both fields are optional when decoding from JSON; by default, at least one request is
+/// expected to match.
+///
+/// ```
+/// # extern crate rusqbin;
+/// # extern crate serde_json;
+/// # use rusqbin::models::*;
+/// # fn main() {
+/// let expectation: Expectation = serde_json::from_str(r#"{
+///   "match": { "method": "POST", "path": "^/webhooks$" },
+///   "count": { "exactly": 1 }
+/// }"#).unwrap();
+/// assert_eq!(expectation.count, ExpectedCount::Exactly(1));
+/// assert!(!expectation.verify(&[]).passed);
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Expectation {
+    #[serde(rename = "match")]
+    pub matcher: RequestMatcher,
+    pub count: ExpectedCount,
+}
+
+impl Expectation {
+    /// Checks that the expectation can be used, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        self.matcher.validate()
+    }
+
+    /// Checks the expectation against the given requests. If it isn't met, the requests that
+    /// came closest to matching are reported along with what kept them from doing so.
+    pub fn verify(&self, requests: &[Request]) -> Verification {
+        let mut matched = 0;
+        let mut near_misses = Vec::new();
+        for request in requests {
+            let mismatches = self.matcher.mismatches(request);
+            if mismatches.is_empty() {
+                matched += 1;
+            } else {
+                near_misses.push(NearMiss {
+                    request: request.clone(),
+                    mismatches,
+                });
+            }
+        }
+        let passed = self.count.allows(matched);
+        if passed {
+            near_misses.clear();
+        } else {
+            // Stable, so requests that are equally close stay oldest first
+            near_misses.sort_by_key(|miss| miss.mismatches.len());
+            near_misses.truncate(MAX_NEAR_MISSES);
+        }
+        Verification {
+            passed,
+            expected: self.count,
+            matched,
+            message: format!(
+                "Expected {} matching request(s), got {}",
+                self.count,
+                matched
+            ),
+            near_misses,
+        }
+    }
+}
+
+/// The outcome of checking an Expectation against a bin's requests.
+#[derive(PartialEq, Debug, Eq, Clone, Serialize)]
+pub struct Verification {
+    pub passed: bool,
+    pub expected: ExpectedCount,
+    /// Number of requests that matched.
+    pub matched: usize,
+    pub message: String,
+    /// The requests that came closest to matching, fewest mismatches first. Only given when
+    /// the expectation wasn't met.
+    pub near_misses: Vec<NearMiss>,
+}
+
+/// A request that didn't match an Expectation, and why.
+#[derive(PartialEq, Debug, Eq, Clone, Serialize)]
+pub struct NearMiss {
+    pub request: Request,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Which of a bin's requests to send again, and where to, e.g. to replay webhooks captured
+/// from a vendor's sandbox against a local build of their receiver.
+///
+/// Only `target` is required when decoding from JSON; by default, every request is replayed,
+/// oldest first.
+///
+/// ```
+/// # extern crate rusqbin;
+/// # extern crate serde_json;
+/// # use rusqbin::models::*;
+/// # fn main() {
+/// let replay: Replay = serde_json::from_str(r#"{
+///   "target": "http://localhost:3000/hooks",
+///   "match": { "method": "POST" }
+/// }"#).unwrap();
+/// assert!(replay.validate().is_ok());
+/// assert!(replay.request_ids.is_empty());
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    /// Base URL that each request's path (and query string) is appended to.
+    pub target: String,
+    /// Ids of the requests to replay. All of them if empty.
+    #[serde(default)]
+    pub request_ids: Vec<Id>,
+    /// Only requests that this matches are replayed.
+    #[serde(rename = "match", default)]
+    pub matcher: RequestMatcher,
+}
+
+impl Replay {
+    /// Checks that the replay can be carried out, returning a description of the problem if
+    /// not.
+    pub fn validate(&self) -> Result<(), String> {
+        validate_base_url("target", &self.target)?;
+        self.matcher.validate()
+    }
+
+    /// Whether the given request is one to replay.
+    pub fn selects(&self, request: &Request) -> bool {
+        (self.request_ids.is_empty() || self.request_ids.contains(&request.id)) &&
+            self.matcher.matches(request)
+    }
+}
+
+/// How replaying a single request went.
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+pub struct ReplayOutcome {
+    /// Id of the replayed request.
+    pub request_id: Id,
+    pub method: String,
+    /// The URL the request was sent to.
+    pub url: String,
+    /// Status the target responded with. None if it couldn't be reached.
+    pub status: Option<u16>,
+    /// What went wrong, if the target couldn't be reached.
+    pub error: Option<String>,
+    /// Milliseconds between sending the request and the target's response coming back.
+    pub latency_ms: u64,
+}
+
+/// A canned response to send back to callers whose requests get captured by a bin.
+///
+/// Only `status` is required when decoding from JSON. The body may be given as a plain
+/// string.
+///
+/// With `template` set, header values and text bodies are templates that get filled in from
+/// each captured request (see the templates module):
+///
+/// ```
+/// # extern crate rusqbin;
+/// # extern crate serde_json;
+/// # use rusqbin::models::*;
+/// # fn main() {
+/// let response: MockResponse = serde_json::from_str(r#"{
+///   "status": 200,
+///   "headers": { "X-Correlation-Id": "{{headers.X-Correlation-Id}}" },
+///   "body": "{\"order\": \"{{path.1}}\"}",
+///   "template": true
+/// }"#).unwrap();
+/// assert!(response.validate().is_ok());
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
+pub struct MockResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: Option<Body>,
+    /// Whether the headers and body are templates.
+    #[serde(default)]
+    pub template: bool,
+}
+
+impl MockResponse {
+    /// Checks that the response can be sent, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        validate_status(self.status)?;
+        if self.template {
+            for value in self.headers.values() {
+                templates::validate(value)?;
+            }
+            if let Some(text) = self.body.as_ref().and_then(|b| b.text()) {
+                templates::validate(text)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the response to send for the given captured request, filling in its templates
+    /// if it has any. Bodies that aren't text are sent as they are.
+    pub fn render(self, request: &Request) -> MockResponse {
+        if !self.template {
+            return self;
+        }
+        let headers = self.headers
+            .iter()
+            .map(|(name, value)| {
+                (name.to_owned(), templates::render_header(value, request))
+            })
+            .collect();
+        let body = self.body.map(|body| match body.text() {
+            Some(text) => Body::from(templates::render(text, request)),
+            None => body,
+        });
+        MockResponse {
+            headers,
+            body,
+            ..self
+        }
+    }
+}
+
+fn validate_status(status: u16) -> Result<(), String> {
+    if !(100..=599).contains(&status) {
+        Err(format!("Invalid response status: {}", status))
+    } else {
+        Ok(())
+    }
 }
 
 /// Summary of a Bin of requests.
 #[derive(PartialEq, Debug, Eq, Serialize, Deserialize)]
 pub struct BinSummary {
     pub id: Id,
+    /// Number of requests currently held in the bin.
     pub request_count: usize,
+    /// Number of requests ever received by the bin, including ones that have been evicted.
+    pub total_request_count: usize,
+    pub max_requests: Option<usize>,
+    /// When the bin was created, in milliseconds since the Unix epoch.
+    pub created_at: i64,
+    /// When the bin will be deleted, in milliseconds since the Unix epoch, if ever.
+    pub expires_at: Option<i64>,
+}
+
+/// Returns the current time in milliseconds since the Unix epoch.
+pub fn now_millis() -> i64 {
+    let now = time::get_time();
+    (now.sec as i64 * 1000) + (now.nsec as i64 / 1000 / 1000)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json;
+    use storage::test_suite::bare_request;
 
     #[test]
     fn test_idextractor_instantiation() {
@@ -134,4 +1269,246 @@ mod tests {
         assert_eq!(decoded, id);
         assert_eq!(format!("\"{}\"", id), encoded); // should be a raw JSON string, not wrapped in an object
     }
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        let mut headers = HashMap::new();
+        headers.insert("x-tenant".to_owned(), vec!["acme".to_owned()]);
+        let mut query_string = HashMap::new();
+        query_string.insert("page".to_owned(), vec!["1".to_owned(), "2".to_owned()]);
+        Request {
+            body: Some(Body::from(body)),
+            headers,
+            query_string,
+            ..bare_request(method, path)
+        }
+    }
+
+    #[test]
+    fn test_request_matcher() {
+        let matcher: RequestMatcher = serde_json::from_str(
+            r#"{
+              "method": "post",
+              "path": "^/orders/[0-9]+$",
+              "query": { "page": "2" },
+              "headers": { "X-Tenant": "acme" },
+              "json": { "/order/status": "paid" }
+            }"#,
+        ).unwrap();
+        let body = r#"{ "order": { "status": "paid" } }"#;
+        assert!(matcher.matches(&request("POST", "/orders/1?page=2", body)));
+        assert!(!matcher.matches(&request("GET", "/orders/1?page=2", body)));
+        assert!(!matcher.matches(&request("POST", "/orders/x?page=2", body)));
+        assert!(!matcher.matches(&request("POST", "/orders/1?page=2", "{}")));
+        assert!(!matcher.matches(&request("POST", "/orders/1?page=2", "not json")));
+        assert!(RequestMatcher::default().matches(&request("GET", "/", "")));
+    }
+
+    #[test]
+    fn test_request_filter() {
+        let mut req = request("POST", "/orders/1/items?page=2", r#"{"id": 42, "status": "paid"}"#);
+        req.time = 1000;
+        let matching = |query: &str| RequestFilter::from_query(query).unwrap().matches(&req);
+        assert!(matching(""));
+        assert!(matching("method=post&path=^/orders"));
+        assert!(matching("path_glob=/orders/*/items"));
+        assert!(matching("path_glob=/**/items"));
+        assert!(!matching("path_glob=/orders/*"));
+        assert!(matching("header=X-TENANT"));
+        assert!(matching("header=x-tenant:acme"));
+        assert!(!matching("header=x-tenant:other"));
+        assert!(!matching("header=x-missing"));
+        assert!(matching("query=page"));
+        assert!(matching("query=page:1"));
+        assert!(!matching("query=size"));
+        assert!(matching("body=paid"));
+        assert!(!matching("body=refunded"));
+        assert!(matching("json=/id:42&json=/status:paid"));
+        assert!(!matching("json=/id:\"42\""));
+        assert!(matching("since=1000&until=1001"));
+        assert!(!matching("since=1001"));
+        assert!(!matching("until=1000"));
+        assert!(RequestFilter::from_query("since=yesterday").is_err());
+        assert!(RequestFilter::from_query("path=(").is_err());
+        assert!(RequestFilter::from_query("json=/id").is_err());
+    }
+
+    #[test]
+    fn test_expectation_verify() {
+        let expectation: Expectation = serde_json::from_str(
+            r#"{
+              "match": {
+                "method": "POST",
+                "path": "^/orders$",
+                "headers": { "X-Tenant": "acme" },
+                "json": { "/status": "paid" }
+              },
+              "count": { "exactly": 1 }
+            }"#,
+        ).unwrap();
+        let paid = request("POST", "/orders", r#"{"status": "paid"}"#);
+        let unpaid = request("POST", "/orders", r#"{"status": "due"}"#);
+        let elsewhere = request("GET", "/refunds", r#"{"status": "paid"}"#);
+
+        let verification = expectation.verify(&[elsewhere.clone(), paid.clone()]);
+        assert!(verification.passed);
+        assert_eq!(verification.matched, 1);
+        assert!(verification.near_misses.is_empty());
+
+        let verification = expectation.verify(&[elsewhere.clone(), unpaid.clone()]);
+        assert!(!verification.passed);
+        assert_eq!(verification.matched, 0);
+        assert_eq!(
+            verification.message,
+            "Expected exactly 1 matching request(s), got 0"
+        );
+        let closest: Vec<&Request> = verification
+            .near_misses
+            .iter()
+            .map(|miss| &miss.request)
+            .collect();
+        assert_eq!(closest, vec![&unpaid, &elsewhere]);
+        assert_eq!(
+            verification.near_misses[0].mismatches,
+            vec![
+                Mismatch {
+                    field: "json",
+                    name: Some("/status".to_owned()),
+                    expected: Value::from("paid"),
+                    actual: Some(Value::from("due")),
+                },
+            ]
+        );
+        assert_eq!(
+            verification.near_misses[1]
+                .mismatches
+                .iter()
+                .map(|m| m.field)
+                .collect::<Vec<&str>>(),
+            vec!["method", "path"]
+        );
+
+        assert!(!expectation.verify(&[paid.clone(), paid.clone()]).passed);
+        let at_most: Expectation = serde_json::from_str(r#"{"count": {"at_most": 1}}"#).unwrap();
+        let both = [paid, unpaid];
+        assert!(at_most.verify(&both[..1]).passed);
+        assert!(!at_most.verify(&both).passed);
+        assert!(!Expectation::default().verify(&[]).passed);
+        assert!(Expectation::default().verify(&both[..1]).passed);
+    }
+
+    #[test]
+    fn test_bin_config_response_for() {
+        let response = |status| {
+            MockResponse {
+                status,
+                headers: HashMap::new(),
+                body: None,
+                template: false,
+            }
+        };
+        let config = BinConfig {
+            response: Some(response(200)),
+            rules: vec![
+                ResponseRule {
+                    matcher: RequestMatcher {
+                        method: Some("DELETE".to_owned()),
+                        ..RequestMatcher::default()
+                    },
+                    response: response(409),
+                },
+            ],
+            ..BinConfig::default()
+        };
+        let delete = request("DELETE", "/", "");
+        let get = request("GET", "/", "");
+        assert_eq!(config.response_for(&delete), Some(&response(409)));
+        assert_eq!(config.response_for(&get), Some(&response(200)));
+        assert_eq!(BinConfig::default().response_for(&get), None);
+    }
+
+    #[test]
+    fn test_bin_config_validation() {
+        let invalid: BinConfig = serde_json::from_str(
+            r#"{ "rules": [ { "match": { "path": "(" }, "response": { "status": 200 } } ] }"#,
+        ).unwrap();
+        assert!(invalid.validate().is_err());
+        let invalid: BinConfig = serde_json::from_str(r#"{ "response": { "status": 42 } }"#)
+            .unwrap();
+        assert!(invalid.validate().is_err());
+        let invalid: BinConfig = serde_json::from_str(r#"{ "faults": { "drop_percent": 101 } }"#)
+            .unwrap();
+        assert!(invalid.validate().is_err());
+        let invalid: BinConfig = serde_json::from_str(
+            r#"{ "faults": { "slow_body": { "chunk_size": 0, "chunk_delay_ms": 10 } } }"#,
+        ).unwrap();
+        assert!(invalid.validate().is_err());
+        for faults in &[
+            r#"{ "delay_ms": 600001 }"#,
+            r#"{ "jitter_ms": 18446744073709551615 }"#,
+            r#"{ "slow_body": { "chunk_size": 1, "chunk_delay_ms": 600001 } }"#,
+        ]
+        {
+            let invalid: Faults = serde_json::from_str(faults).unwrap();
+            assert!(invalid.validate().is_err());
+        }
+        let longest: Faults =
+            serde_json::from_str(r#"{ "delay_ms": 600000, "jitter_ms": 600000 }"#).unwrap();
+        assert!(longest.validate().is_ok());
+        let scenario = |initial: &str, next: &str| {
+            format!(
+                r#"{{ "name": "s", "initial_state": "{}",
+                     "states": {{ "a": {{ "response": {{ "status": 200 }}, "next": "{}" }} }} }}"#,
+                initial,
+                next
+            )
+        };
+        let scenarios = |scenarios: Vec<String>| -> BinConfig {
+            serde_json::from_str(&format!(r#"{{ "scenarios": [{}] }}"#, scenarios.join(",")))
+                .unwrap()
+        };
+        assert!(scenarios(vec![scenario("a", "a")]).validate().is_ok());
+        assert!(scenarios(vec![scenario("b", "a")]).validate().is_err());
+        assert!(scenarios(vec![scenario("a", "b")]).validate().is_err());
+        assert!(
+            scenarios(vec![scenario("a", "a"), scenario("a", "a")])
+                .validate()
+                .is_err()
+        );
+        assert!(BinConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_bin_options() {
+        let options = |ttl_secs| {
+            BinOptions {
+                ttl_secs,
+                ..BinOptions::default()
+            }
+        };
+        assert!(options(Some(MAX_TTL_SECS)).validate().is_ok());
+        assert!(options(Some(MAX_TTL_SECS + 1)).validate().is_err());
+        assert!(options(Some(0)).validate().is_err());
+        assert_eq!(options(None).expires_at(1000), None);
+        assert_eq!(options(Some(2)).expires_at(1000), Some(3000));
+        assert_eq!(options(Some(u64::MAX)).expires_at(1000), Some(i64::MAX));
+        assert_eq!(
+            options(Some(i64::MAX as u64 / 1000)).expires_at(i64::MAX - 1),
+            Some(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_body_json_encoding_decoding() {
+        let body = Body::new(vec![0, 159, 146, 150]);
+        let encoded = serde_json::to_string(&body).unwrap();
+        assert_eq!(encoded, r#"{"encoding":"base64","data":"AJ+Slg=="}"#);
+        let decoded: Body = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded, body);
+    }
+
+    #[test]
+    fn test_body_json_decoding_from_plain_string() {
+        let decoded: Body = serde_json::from_str("\"hello\"").unwrap();
+        assert_eq!(decoded.text(), Some("hello"));
+    }
 }
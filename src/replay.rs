@@ -0,0 +1,105 @@
+//! Sends captured requests on to other servers.
+//!
+//! Requests keep their method, path (including the query string), headers and body, apart from
+//! headers that only concern the connection they came in on, and are sent to that path under
+//! a given base URL. This is what forwards requests to a bin's upstream, and what replays a
+//! bin's requests against a target.
+use std::time::Instant;
+
+use hyper;
+use hyper::client::Request as HyperRequest;
+use futures::{future, stream, Future, Stream};
+use tokio_core::reactor::Handle;
+
+use models::{Replay, ReplayOutcome, Request};
+use errors::Error;
+
+/// Headers that are not passed on, as they only concern a single connection or are set by
+/// rusqbin itself.
+const UNFORWARDED_HEADERS: [&str; 5] = [
+    "host",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "x-rusqbin-id",
+];
+
+/// Whether a header can be passed on between a client and another server as it is.
+pub fn is_forwardable_header(name: &str) -> bool {
+    !UNFORWARDED_HEADERS.iter().any(
+        |h| h.eq_ignore_ascii_case(name),
+    )
+}
+
+/// The URL a captured request is sent to under the given base URL.
+pub fn target_url(base_url: &str, request: &Request) -> String {
+    format!("{}{}", base_url.trim_end_matches('/'), request.path)
+}
+
+/// Builds a request to send the given captured request to the given URL.
+pub fn build_request(request: &Request, url: &str) -> Result<HyperRequest, hyper::Error> {
+    let uri = url.parse::<hyper::Uri>()?;
+    let method = request.method.parse()?;
+    let mut built = HyperRequest::new(method, uri);
+    for (name, values) in &request.headers {
+        if !is_forwardable_header(name) {
+            continue;
+        }
+        for value in values {
+            built.headers_mut().append_raw(name.to_owned(), value.to_owned());
+        }
+    }
+    if let Some(ref body) = request.body {
+        built.set_body(body.bytes().to_vec());
+    }
+    Ok(built)
+}
+
+/// Sends each of the given requests that the Replay selects to its target, one after the
+/// other so that they arrive in the order given, reporting how each went.
+///
+/// Requests that fail to go through are reported as such rather than stopping the replay.
+pub fn replay(
+    requests: Vec<Request>,
+    replay: &Replay,
+    handle: &Handle,
+) -> Box<dyn Future<Item = Vec<ReplayOutcome>, Error = Error>> {
+    let client = hyper::Client::new(handle);
+    let target = replay.target.to_owned();
+    let selected: Vec<Request> = requests.into_iter().filter(|r| replay.selects(r)).collect();
+    let f = stream::iter_ok(selected)
+        .and_then(move |request| {
+            let url = target_url(&target, &request);
+            let sent_at = Instant::now();
+            let response = match build_request(&request, &url) {
+                Ok(built) => {
+                    debug!("Replaying request {} to {}", request.id, url);
+                    future::Either::A(client.request(built).and_then(|res| {
+                        let status = res.status();
+                        res.body().concat2().map(move |_| status)
+                    }))
+                }
+                Err(e) => future::Either::B(future::err(e)),
+            };
+            response.then(move |result| {
+                let elapsed = sent_at.elapsed();
+                let (status, error) = match result {
+                    Ok(status) => (Some(status.as_u16()), None),
+                    Err(e) => {
+                        info!("Replaying request {} to {} failed: {}", request.id, url, e);
+                        (None, Some(e.to_string()))
+                    }
+                };
+                Ok(ReplayOutcome {
+                    request_id: request.id,
+                    method: request.method,
+                    url,
+                    status,
+                    error,
+                    latency_ms: elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()),
+                })
+            })
+        })
+        .collect();
+    Box::new(f)
+}
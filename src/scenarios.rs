@@ -0,0 +1,129 @@
+//! Defines ScenarioStates, which keeps track of which state each bin's scenarios are in.
+//!
+//! Scenarios themselves are part of a bin's BinConfig, so they are kept by storage, but their
+//! states only live as long as the server does: they start over whenever the server restarts
+//! or a bin's config is replaced.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use models::{BinConfig, Id, MockResponse, Request};
+
+/// Keeps track of the current state of every bin's scenarios.
+///
+/// Cloning a ScenarioStates gives another handle on the same states.
+#[derive(Clone, Default)]
+pub struct ScenarioStates {
+    /// Bin Id -> scenario name -> current state, for scenarios that have left their initial
+    /// state.
+    states: Arc<Mutex<HashMap<Id, HashMap<String, String>>>>,
+}
+
+impl ScenarioStates {
+    pub fn new() -> ScenarioStates {
+        ScenarioStates::default()
+    }
+
+    /// Returns the current state of each of the given bin's scenarios, by scenario name.
+    pub fn current(&self, id: &Id, config: &BinConfig) -> HashMap<String, String> {
+        let states = self.states.lock().ok();
+        let bin_states = states.as_ref().and_then(|states| states.get(id));
+        config
+            .scenarios
+            .iter()
+            .map(|scenario| {
+                let state = bin_states
+                    .and_then(|s| s.get(&scenario.name))
+                    .filter(|state| scenario.states.contains_key(*state))
+                    .unwrap_or(&scenario.initial_state);
+                (scenario.name.to_owned(), state.to_owned())
+            })
+            .collect()
+    }
+
+    /// Picks the response to a request captured by the given bin from the first of its
+    /// scenarios that the request matches, moving that scenario on to its next state. None if
+    /// no scenario matches.
+    pub fn respond(&self, id: &Id, config: &BinConfig, request: &Request) -> Option<MockResponse> {
+        let scenario = config.scenarios.iter().find(
+            |scenario| scenario.matcher.matches(request),
+        )?;
+        let mut states = self.states.lock().ok()?;
+        let bin_states = states.entry(id.to_owned()).or_default();
+        let current = bin_states
+            .get(&scenario.name)
+            .filter(|state| scenario.states.contains_key(*state))
+            .unwrap_or(&scenario.initial_state)
+            .to_owned();
+        let (response, next) = scenario.step(&current)?;
+        debug!(
+            "Scenario {} of bin {} moving from {} to {}",
+            scenario.name,
+            id,
+            current,
+            next
+        );
+        bin_states.insert(scenario.name.to_owned(), next.to_owned());
+        Some(response.clone())
+    }
+
+    /// Puts all of the given bin's scenarios back in their initial states.
+    pub fn reset(&self, id: &Id) {
+        if let Ok(mut states) = self.states.lock() {
+            states.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::Body;
+    use serde_json;
+    use storage::test_suite::bare_request;
+
+    fn request(path: &str) -> Request {
+        Request {
+            body: Some(Body::from("{}")),
+            ..bare_request("POST", path)
+        }
+    }
+
+    #[test]
+    fn test_scenario_states() {
+        let config: BinConfig = serde_json::from_str(
+            r#"{
+              "scenarios": [
+                {
+                  "name": "flaky",
+                  "match": { "path": "^/payments$" },
+                  "initial_state": "down",
+                  "states": {
+                    "down": { "response": { "status": 503 }, "next": "up" },
+                    "up": { "response": { "status": 200 } }
+                  }
+                }
+              ]
+            }"#,
+        ).unwrap();
+        let scenarios = ScenarioStates::new();
+        let (id, other) = (Id::random(), Id::random());
+        let status = |id: &Id, path: &str| {
+            scenarios.respond(id, &config, &request(path)).map(
+                |r| r.status,
+            )
+        };
+        assert_eq!(status(&id, "/elsewhere"), None);
+        assert_eq!(scenarios.current(&id, &config)["flaky"], "down");
+        assert_eq!(status(&id, "/payments"), Some(503));
+        assert_eq!(scenarios.current(&id, &config)["flaky"], "up");
+        assert_eq!(status(&id, "/payments"), Some(200));
+        assert_eq!(status(&id, "/payments"), Some(200));
+        // Every bin has its own states
+        assert_eq!(status(&other, "/payments"), Some(503));
+
+        scenarios.reset(&id);
+        assert_eq!(scenarios.current(&id, &config)["flaky"], "down");
+        assert_eq!(status(&id, "/payments"), Some(503));
+        assert_eq!(scenarios.current(&other, &config)["flaky"], "up");
+    }
+}
@@ -1,40 +1,80 @@
 //! Defines a BinsServer, which can serve requests against bins.
 //!
-//! BinsServer holds a database and wraps Hyper's Http server.
+//! BinsServer holds a database and wraps Hyper's Http server. It also runs a reaper in the
+//! background that deletes bins once their TTL has passed.
+//!
+//! Responses to captured requests are chosen according to each bin's BinConfig, including any
+//! latency or faults it asks to be injected.
 use std::sync::{Mutex, Arc};
 use std::collections::HashMap;
+use std::time::Duration;
 
 use hyper;
 use hyper::server::{Http, Request, Response, Service};
 use hyper::header::ContentLength;
-use hyper::header::Headers;
-use hyper::{Get, Post, Delete};
+use hyper::header::{CacheControl, CacheDirective, Headers, Host};
+use hyper::{Get, Post, Put, Delete};
 use hyper::StatusCode;
 
 use storage::*;
+use events::{BinEvent, BinEvents};
+use scenarios::ScenarioStates;
+use replay;
+use feed::serve_feed;
 use models;
-use models::{Id, IdExtractor};
+use models::{now_millis, BinConfig, BinOptions, Expectation, Faults, Id, IdExtractor,
+             MockResponse, RecordedResponse, Replay, RequestFilter, SlowBody};
 
 use errors;
 use errors::*;
 
-use regex::Regex;
+use regex::{self, Regex};
 
 use serde::*;
 use serde_json;
 
-use time;
 
-use url::Url;
+use url::{form_urlencoded, Url};
+
+use futures::{future, stream, Future, Sink, Stream};
+use futures::future::Either;
+use futures::sync::mpsc::UnboundedReceiver;
+
+use tokio_core::reactor::{Core, Handle, Interval, Timeout};
 
-use futures::{future, Future, Stream};
+use rand::{self, Rng};
+
+/// Matches a bin Id, as used in paths.
+pub const BIN_ID_PATTERN: &str =
+    r"(?i)[A-F0-9]{8}\-[A-F0-9]{4}\-4[A-F0-9]{3}\-[89AB][A-F0-9]{3}\-[A-F0-9]{12}";
 
 lazy_static! {
     static ref BIN_SUMMARY_PATH_REGEXP: Regex = {
-        Regex::new(r"/rusqbins/((?i)[A-F0-9]{8}\-[A-F0-9]{4}\-4[A-F0-9]{3}\-[89AB][A-F0-9]{3}\-[A-F0-9]{12})$").unwrap()
+        Regex::new(&format!(r"/rusqbins/({})$", BIN_ID_PATTERN)).unwrap()
     };
     static ref BIN_REQUESTS_PATH_REGEXP: Regex = {
-        Regex::new(r"/rusqbins/((?i)[A-F0-9]{8}\-[A-F0-9]{4}\-4[A-F0-9]{3}\-[89AB][A-F0-9]{3}\-[A-F0-9]{12})/requests/?$").unwrap()
+        Regex::new(&format!(r"/rusqbins/({})/requests/?$", BIN_ID_PATTERN)).unwrap()
+    };
+    static ref BIN_REQUEST_PATH_REGEXP: Regex = {
+        Regex::new(&format!(r"/rusqbins/({})/requests/({})/?$", BIN_ID_PATTERN, BIN_ID_PATTERN)).unwrap()
+    };
+    static ref BIN_WAIT_PATH_REGEXP: Regex = {
+        Regex::new(&format!(r"/rusqbins/({})/requests/wait/?$", BIN_ID_PATTERN)).unwrap()
+    };
+    static ref BIN_STREAM_PATH_REGEXP: Regex = {
+        Regex::new(&format!(r"/rusqbins/({})/stream/?$", BIN_ID_PATTERN)).unwrap()
+    };
+    static ref BIN_VERIFY_PATH_REGEXP: Regex = {
+        Regex::new(&format!(r"/rusqbins/({})/verify/?$", BIN_ID_PATTERN)).unwrap()
+    };
+    static ref BIN_REPLAY_PATH_REGEXP: Regex = {
+        Regex::new(&format!(r"/rusqbins/({})/replay/?$", BIN_ID_PATTERN)).unwrap()
+    };
+    static ref BIN_SCENARIOS_PATH_REGEXP: Regex = {
+        Regex::new(&format!(r"/rusqbins/({})/scenarios/?$", BIN_ID_PATTERN)).unwrap()
+    };
+    static ref BIN_CONFIG_PATH_REGEXP: Regex = {
+        Regex::new(&format!(r"/rusqbins/({})/config/?$", BIN_ID_PATTERN)).unwrap()
     };
 }
 
@@ -46,6 +86,43 @@ where
     pub address: String,
     pub port: usize,
     pub storage: Arc<Mutex<T>>,
+    pub config: ServerConfig,
+}
+
+/// Server-wide settings for a BinsServer.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Maximum number of requests kept in bins that are created without their own limit.
+    pub default_max_requests: Option<usize>,
+    /// TTL in seconds for bins that are created without their own TTL.
+    pub default_ttl_secs: Option<u64>,
+    /// How often to look for, and delete, expired bins.
+    pub reap_interval: Duration,
+    /// Prefix for paths that route requests to a bin: requests to `{prefix}/{bin_id}/...` are
+    /// captured by that bin, as an alternative to sending an X-Rusqbin-Id header. Empty by
+    /// default, so `/{bin_id}/...` works out of the box.
+    pub bin_path_prefix: String,
+    /// If set, requests whose Host is `{bin_id}.{bin_base_domain}` are captured by that bin.
+    pub bin_base_domain: Option<String>,
+    /// If set, requests with this query parameter set to a bin Id are captured by that bin.
+    /// The parameter itself is not recorded. `rusqbin_id` by default.
+    pub bin_query_param: Option<String>,
+    /// If set, serves a WebSocket feed of captured requests on this port. See the feed module.
+    pub websocket_port: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            default_max_requests: None,
+            default_ttl_secs: None,
+            reap_interval: Duration::from_secs(1),
+            bin_path_prefix: String::new(),
+            bin_base_domain: None,
+            bin_query_param: Some("rusqbin_id".to_owned()),
+            websocket_port: None,
+        }
+    }
 }
 
 /// A Worker handles requests on the server and holds on to some
@@ -57,7 +134,19 @@ where
     id_extractor: IdExtractor,
     bin_summary_path_regexp: Regex,
     bin_requests_path_regexp: Regex,
+    bin_request_path_regexp: Regex,
+    bin_wait_path_regexp: Regex,
+    bin_stream_path_regexp: Regex,
+    bin_verify_path_regexp: Regex,
+    bin_replay_path_regexp: Regex,
+    bin_scenarios_path_regexp: Regex,
+    bin_config_path_regexp: Regex,
+    bin_path_regexp: Regex,
     bins: Arc<Mutex<T>>,
+    config: Arc<ServerConfig>,
+    handle: Handle,
+    events: BinEvents,
+    scenarios: ScenarioStates,
 }
 
 header! { (ContentType, "Content-Type") => [String] }
@@ -87,27 +176,64 @@ where
                     future_result(self.delete_bin(path))
                 }
                 (&Get, path) if self.extract_id_from_bin_requests_path(path).is_some() => {
-                    future_result(self.find_bin_requests(path))
+                    future_result(self.find_bin_requests(path, req.query()))
+                }
+                (&Delete, path) if self.extract_id_from_bin_requests_path(path).is_some() => {
+                    future_result(self.clear_bin_requests(path))
+                }
+                (&Get, path) if self.extract_ids_from_bin_request_path(path).is_some() => {
+                    future_result(self.find_request(path))
+                }
+                (&Delete, path) if self.extract_ids_from_bin_request_path(path).is_some() => {
+                    future_result(self.delete_request(path))
+                }
+                (&Get, path) if self.extract_id_from_bin_wait_path(path).is_some() => {
+                    self.wait_for_requests(path, req.query())
+                }
+                (&Get, path) if self.extract_id_from_bin_stream_path(path).is_some() => {
+                    future_result(self.stream_bin_requests(path, req.query()))
+                }
+                (&Post, path) if self.extract_id_from_bin_verify_path(path).is_some() => {
+                    self.verify_requests(path, req)
+                }
+                (&Post, path) if self.extract_id_from_bin_replay_path(path).is_some() => {
+                    self.replay_requests(path, req)
+                }
+                (&Get, path) if self.extract_id_from_bin_scenarios_path(path).is_some() => {
+                    future_result(self.find_scenario_states(path))
+                }
+                (&Delete, path) if self.extract_id_from_bin_scenarios_path(path).is_some() => {
+                    future_result(self.reset_scenario_states(path))
+                }
+                (&Get, path) if self.extract_id_from_bin_config_path(path).is_some() => {
+                    future_result(self.find_bin_config(path))
+                }
+                (&Put, path) if self.extract_id_from_bin_config_path(path).is_some() => {
+                    self.update_bin_config(path, req)
                 }
                 (&Get, "/rusqbins") |
                 (&Get, "/rusqbins/") => future_result(self.list_bins()),
                 (&Post, "/rusqbins") |
-                (&Post, "/rusqbins/") => future_result(self.create_bin()),
+                (&Post, "/rusqbins/") => self.create_bin(req),
                 _ if self.extract_id_from_header(req.headers()).is_some() => {
                     Box::new((self.insert_request(req)))
                 }
+                _ if self.extract_id_from_host(req.headers()).is_some() => {
+                    self.insert_request(req)
+                }
+                _ if self.extract_id_from_query(req.query()).is_some() => self.insert_request(req),
+                (_, path) if self.extract_id_from_bin_path(path).is_some() => {
+                    self.insert_request(req)
+                }
                 _ => future_result(bad_request(Response::new())),
             }
         };
         Box::new(result_future.then(
             |handling_result| match handling_result {
                 Err(Error::PoisonedLock) => panic!("Yo. Mutex got poisoned. Now wut?"),
-                Err(e) => {
-                    error!("Something really messed up bad: {:?}", e);
-                    let mut res = Response::new();
-                    res.set_status(StatusCode::InternalServerError);
-                    future::ok(res)
-                }
+                // Failing the future is how we get Hyper to close the connection.
+                Err(Error::ConnectionDropped) => future::err(hyper::Error::Incomplete),
+                Err(e) => future::ok(error_response(e)),
                 Ok(rsp) => future::ok(rsp),
             },
         ))
@@ -139,21 +265,173 @@ where
         })
     }
 
+    fn extract_id_from_bin_wait_path<'a>(&'a self, s: &'a str) -> Option<Id> {
+        let caps = self.bin_wait_path_regexp.captures(s);
+        caps.and_then(|c| {
+            c.get(1).and_then(|r| self.id_extractor.parse(r.as_str()))
+        })
+    }
+
+    fn extract_id_from_bin_stream_path<'a>(&'a self, s: &'a str) -> Option<Id> {
+        let caps = self.bin_stream_path_regexp.captures(s);
+        caps.and_then(|c| {
+            c.get(1).and_then(|r| self.id_extractor.parse(r.as_str()))
+        })
+    }
+
+    fn extract_id_from_bin_verify_path<'a>(&'a self, s: &'a str) -> Option<Id> {
+        let caps = self.bin_verify_path_regexp.captures(s);
+        caps.and_then(|c| {
+            c.get(1).and_then(|r| self.id_extractor.parse(r.as_str()))
+        })
+    }
+
+    fn extract_id_from_bin_replay_path<'a>(&'a self, s: &'a str) -> Option<Id> {
+        let caps = self.bin_replay_path_regexp.captures(s);
+        caps.and_then(|c| {
+            c.get(1).and_then(|r| self.id_extractor.parse(r.as_str()))
+        })
+    }
+
+    fn extract_id_from_bin_scenarios_path<'a>(&'a self, s: &'a str) -> Option<Id> {
+        let caps = self.bin_scenarios_path_regexp.captures(s);
+        caps.and_then(|c| {
+            c.get(1).and_then(|r| self.id_extractor.parse(r.as_str()))
+        })
+    }
+
+    /// Extracts the bin Id and the request Id from the path of a single request.
+    fn extract_ids_from_bin_request_path<'a>(&'a self, s: &'a str) -> Option<(Id, Id)> {
+        let caps = self.bin_request_path_regexp.captures(s);
+        caps.and_then(|c| {
+            let bin_id = c.get(1).and_then(|r| self.id_extractor.parse(r.as_str()));
+            let request_id = c.get(2).and_then(|r| self.id_extractor.parse(r.as_str()));
+            bin_id.and_then(|bin_id| request_id.map(|request_id| (bin_id, request_id)))
+        })
+    }
+
+    fn extract_id_from_bin_config_path<'a>(&'a self, s: &'a str) -> Option<Id> {
+        let caps = self.bin_config_path_regexp.captures(s);
+        caps.and_then(|c| {
+            c.get(1).and_then(|r| self.id_extractor.parse(r.as_str()))
+        })
+    }
+
     fn extract_id_from_header<'a>(&'a self, headers: &'a Headers) -> Option<Id> {
         headers.get::<XRusqBinId>().and_then(
             |s| self.id_extractor.parse(s),
         )
     }
+
+    /// Extracts the Id from a Host of the form `{bin_id}.{bin_base_domain}`, if a base domain
+    /// has been configured.
+    fn extract_id_from_host<'a>(&'a self, headers: &'a Headers) -> Option<Id> {
+        let base_domain = match self.config.bin_base_domain {
+            Some(ref base_domain) => base_domain.trim_matches('.'),
+            None => return None,
+        };
+        headers.get::<Host>().and_then(|host| {
+            let hostname = host.hostname();
+            let split = hostname.len().checked_sub(base_domain.len() + 1)?;
+            let (label, domain) = (hostname.get(..split)?, hostname.get(split..)?);
+            if domain.starts_with('.') && domain[1..].eq_ignore_ascii_case(base_domain) {
+                self.id_extractor.parse(label)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Extracts the Id from the configured query parameter, along with the rest of the query
+    /// string without that parameter. The rest is kept exactly as it was sent.
+    fn extract_id_from_query(&self, query: Option<&str>) -> Option<(Id, String)> {
+        let (param, query) = match (self.config.bin_query_param.as_ref(), query) {
+            (Some(param), Some(query)) => (param, query),
+            _ => return None,
+        };
+        let mut id = None;
+        let mut rest = Vec::new();
+        for pair in query.split('&') {
+            match form_urlencoded::parse(pair.as_bytes()).next() {
+                Some((ref k, ref v)) if k == param => {
+                    id = id.or_else(|| self.id_extractor.parse(v));
+                }
+                _ => rest.push(pair),
+            }
+        }
+        id.map(|id| (id, rest.join("&")))
+    }
+
+    /// Extracts the Id from a path that routes to a bin, along with the rest of the path
+    /// after it.
+    fn extract_id_from_bin_path<'a>(&'a self, s: &'a str) -> Option<(Id, String)> {
+        let caps = self.bin_path_regexp.captures(s);
+        caps.and_then(|c| {
+            c.get(1).and_then(|r| self.id_extractor.parse(r.as_str())).map(
+                |id| {
+                    let rest = c.get(2).map_or("", |r| r.as_str());
+                    (id, if rest.is_empty() { "/" } else { rest }.to_owned())
+                },
+            )
+        })
+    }
+    /// Works out which bin a request to be captured should go to, along with the path to
+    /// record for it if that differs from the requested one.
+    ///
+    /// The header takes precedence, then the Host, then the query parameter and finally the
+    /// path. When routed by query parameter, the parameter is left out of what gets recorded;
+    /// when routed by path, only the rest of the path after the Id is.
+    fn find_target_bin(&self, req: &Request) -> Option<(Id, Option<String>)> {
+        if let Some(id) = self.extract_id_from_header(req.headers()).or_else(|| {
+            self.extract_id_from_host(req.headers())
+        })
+        {
+            Some((id, None))
+        } else if let Some((id, query)) = self.extract_id_from_query(req.query()) {
+            let path = if query.is_empty() {
+                req.path().to_owned()
+            } else {
+                format!("{}?{}", req.path(), query)
+            };
+            Some((id, Some(path)))
+        } else {
+            self.extract_id_from_bin_path(req.path()).map(|(id, rest)| {
+                let path = match req.query() {
+                    Some(query) => format!("{}?{}", rest, query),
+                    None => rest,
+                };
+                (id, Some(path))
+            })
+        }
+    }
     // Routing-related helper functions -->
 
     // <-- "Controller" methods.
 
-    fn create_bin(&self) -> Result<Response, Error> {
-        let res = Response::new();
-        let mut cont = self.bins.lock()?;
-        let new_bin = cont.create_bin();
-        info!("Created a new bin {:?}", new_bin);
-        write_json(&new_bin, res)
+    fn create_bin(&self, req: Request) -> Box<dyn Future<Item = Response, Error = Error>> {
+        let bins = self.bins.clone();
+        let config = self.config.clone();
+        Box::new(read_to_bytes(req).and_then(move |body| {
+            let res = Response::new();
+            // An empty body just means "use the defaults"
+            let mut options: BinOptions = if body.iter().all(|b| b.is_ascii_whitespace()) {
+                BinOptions::default()
+            } else {
+                match serde_json::from_slice(&body) {
+                    Ok(options) => options,
+                    Err(e) => return bad_request_json(format!("Invalid bin options: {}", e)),
+                }
+            };
+            if let Err(e) = options.validate() {
+                return bad_request_json(e);
+            }
+            options.max_requests = options.max_requests.or(config.default_max_requests);
+            options.ttl_secs = options.ttl_secs.or(config.default_ttl_secs);
+            let mut cont = bins.lock()?;
+            let new_bin = cont.create_bin_with_options(&options)?;
+            info!("Created a new bin {:?}", new_bin);
+            write_json(&new_bin, res)
+        }))
     }
 
     fn delete_bin(&self, path: &str) -> Result<Response, Error> {
@@ -161,9 +439,11 @@ where
         if let Some(id) = self.extract_id_from_bin_summary_path(path) {
             debug!("Trying to delete a bin with id: {}", id);
             let mut cont = self.bins.lock()?;
-            match cont.delete_bin(&id) {
+            match cont.delete_bin(&id)? {
                 DeleteBinStatus::Ok => {
                     info!("Deleted bin with id: {}", id);
+                    self.events.close(&id);
+                    self.scenarios.reset(&id);
                     ok(res)
                 }
                 DeleteBinStatus::NoSuchBin => {
@@ -180,7 +460,7 @@ where
     fn list_bins(&self) -> Result<Response, Error> {
         let res = Response::new();
         let cont = self.bins.lock()?;
-        let all = &cont.get_bin_summaries();
+        let all = &cont.get_bin_summaries()?;
         info!("Retrieved all bins: {:?}", all);
         write_json(all, res)
     }
@@ -190,7 +470,7 @@ where
         if let Some(id) = self.extract_id_from_bin_summary_path(path) {
             debug!("Trying to find a bin with id: {}", id);
             let cont = self.bins.lock()?;
-            match cont.get_bin_summary(&id) {
+            match cont.get_bin_summary(&id)? {
                 Some(ref bin) => {
                     info!("Retrieved bin summary: {:?}", bin);
                     write_json(bin, res)
@@ -206,15 +486,290 @@ where
         }
     }
 
-    fn find_bin_requests(&self, path: &str) -> Result<Response, Error> {
-        let res = Response::new();
+    /// Answers with the requests in a bin, narrowed down by the RequestFilter in the query
+    /// string, if any, a page at a time if asked to (see PageParams).
+    fn find_bin_requests(&self, path: &str, query: Option<&str>) -> Result<Response, Error> {
+        let mut res = Response::new();
         if let Some(id) = self.extract_id_from_bin_requests_path(path) {
+            let (filter, page) = match (
+                RequestFilter::from_query(query.unwrap_or("")),
+                PageParams::parse(query.unwrap_or("")),
+            ) {
+                (Ok(filter), Ok(page)) => (filter, page),
+                (Err(e), _) | (_, Err(e)) => return bad_request_json(e),
+            };
+            // Without a filter, storage can skip straight to the page; with one, everything
+            // from the cursor on has to be looked at. One extra request tells us if there is
+            // more.
+            let unfiltered = filter == RequestFilter::default();
+            let range = RequestRange {
+                from_seq: page.cursor,
+                offset: if unfiltered { page.offset } else { 0 },
+                limit: if unfiltered { page.limit.map(|l| l.saturating_add(1)) } else { None },
+                order: page.order,
+            };
             debug!("Trying to find a bin with id: {} ", id);
             let cont = self.bins.lock()?;
-            match cont.get_bin(&id) {
-                Some(ref bin) => {
-                    info!("Retrieved bin: {:?}", bin);
-                    write_json(bin, res)
+            let requests = match cont.get_bin_range(&id, &range)? {
+                Some(requests) => requests,
+                None => {
+                    info!("No bin with that id: {}", id);
+                    return not_found(res);
+                }
+            };
+            let mut bin: Vec<models::Request> = requests
+                .into_iter()
+                .filter(|r| filter.matches(r))
+                .skip(if unfiltered { 0 } else { page.offset })
+                .take(page.limit.map_or(usize::MAX, |l| l.saturating_add(1)))
+                .collect();
+            if let Some(limit) = page.limit {
+                if bin.len() > limit {
+                    bin.truncate(limit);
+                    if let Some(last) = bin.last() {
+                        // Cursors are sequence numbers, so they stay put as requests come and go.
+                        let cursor = match page.order {
+                            Order::Asc => last.seq + 1,
+                            Order::Desc => last.seq - 1,
+                        };
+                        res.headers_mut().set_raw(
+                            "Link",
+                            format!("<{}>; rel=\"next\"", next_page_uri(path, query, cursor)),
+                        );
+                        res.headers_mut().set_raw("X-Next-Cursor", cursor.to_string());
+                    }
+                }
+            }
+            info!("Retrieved bin: {:?}", bin);
+            write_json(&bin, res)
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Err(Error::UnforeseenError)
+        }
+    }
+
+    fn find_request(&self, path: &str) -> Result<Response, Error> {
+        let res = Response::new();
+        if let Some((id, request_id)) = self.extract_ids_from_bin_request_path(path) {
+            debug!("Trying to find request {} in bin with id: {}", request_id, id);
+            let cont = self.bins.lock()?;
+            match cont.get_request(&id, &request_id)? {
+                Some(ref request) => {
+                    info!("Retrieved request: {:?}", request);
+                    write_json(request, res)
+                }
+                None => {
+                    info!("No request {} in bin with id: {}", request_id, id);
+                    not_found(res)
+                }
+            }
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Err(Error::UnforeseenError)
+        }
+    }
+
+    fn delete_request(&self, path: &str) -> Result<Response, Error> {
+        let res = Response::new();
+        if let Some((id, request_id)) = self.extract_ids_from_bin_request_path(path) {
+            debug!("Trying to delete request {} in bin with id: {}", request_id, id);
+            let mut cont = self.bins.lock()?;
+            match cont.delete_request(&id, &request_id)? {
+                DeleteRequestStatus::Ok => {
+                    info!("Deleted request {} in bin with id: {}", request_id, id);
+                    ok(res)
+                }
+                DeleteRequestStatus::NoSuchRequest => {
+                    info!("No request {} in bin with id: {}", request_id, id);
+                    not_found(res)
+                }
+            }
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Err(Error::UnforeseenError)
+        }
+    }
+
+    fn clear_bin_requests(&self, path: &str) -> Result<Response, Error> {
+        let res = Response::new();
+        if let Some(id) = self.extract_id_from_bin_requests_path(path) {
+            debug!("Trying to clear requests in bin with id: {}", id);
+            let mut cont = self.bins.lock()?;
+            match cont.clear_bin(&id)? {
+                ClearBinStatus::Ok => {
+                    info!("Cleared requests in bin with id: {}", id);
+                    ok(res)
+                }
+                ClearBinStatus::NoSuchBin => {
+                    info!("No bin with id: {}", id);
+                    not_found(res)
+                }
+            }
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Err(Error::UnforeseenError)
+        }
+    }
+
+    /// Streams requests captured by a bin as Server-Sent Events, starting with those already in
+    /// it that were captured at or after the `since` query parameter (in milliseconds since the
+    /// Unix epoch), if given.
+    fn stream_bin_requests(&self, path: &str, query: Option<&str>) -> Result<Response, Error> {
+        let res = Response::new();
+        if let Some(id) = self.extract_id_from_bin_stream_path(path) {
+            let mut since = None;
+            for (k, v) in form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+                if k == "since" {
+                    match v.parse::<i64>() {
+                        Ok(time) => since = Some(time),
+                        Err(_) => return bad_request_json(format!("Invalid since: {}", v)),
+                    }
+                }
+            }
+            let (backlog, arrivals) = {
+                let cont = self.bins.lock()?;
+                match read_and_subscribe(&*cont, &self.events, &id)? {
+                    Some((bin, arrivals)) => {
+                        let backlog: Vec<models::Request> = match since {
+                            Some(since) => bin.into_iter().filter(|r| r.time >= since).collect(),
+                            None => vec![],
+                        };
+                        (backlog, arrivals)
+                    }
+                    None => {
+                        info!("No bin with that id: {}", id);
+                        return not_found(res);
+                    }
+                }
+            };
+            info!("Streaming requests of bin with id: {}", id);
+            let (tx, body) = hyper::Body::pair();
+            // A comment to start with, so that clients know they are connected.
+            let events = stream::once(Ok(": connected\n\n".to_owned()))
+                .chain(stream::iter_ok(backlog).chain(arrivals.map(|event| event.request)).and_then(
+                    |r| sse_event("request", &r),
+                ))
+                .map(|event| Ok(event.into()));
+            self.handle.spawn(
+                tx.sink_map_err(|_| debug!("Client stopped listening to a stream"))
+                    .send_all(events)
+                    .map(|_| ()),
+            );
+            let mut res = res.with_body(body);
+            res.headers_mut().set(
+                ContentType("text/event-stream".to_owned()),
+            );
+            res.headers_mut().set(CacheControl(
+                vec![CacheDirective::NoCache],
+            ));
+            Ok(res)
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Err(Error::UnforeseenError)
+        }
+    }
+
+    fn wait_for_requests(
+        &self,
+        path: &str,
+        query: Option<&str>,
+    ) -> Box<dyn Future<Item = Response, Error = Error>> {
+        if let Some(id) = self.extract_id_from_bin_wait_path(path) {
+            match WaitParams::parse(query) {
+                Ok(params) => {
+                    match self.start_waiting(id, params) {
+                        Ok(f) => f,
+                        Err(e) => Box::new(future::err(e)),
+                    }
+                }
+                Err(e) => future_result(bad_request_json(e)),
+            }
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Box::new(future::err(Error::UnforeseenError))
+        }
+    }
+
+    /// Answers with the bin's requests matching `params` once there are enough of them,
+    /// waiting for more to be captured if need be.
+    fn start_waiting(
+        &self,
+        id: Id,
+        params: WaitParams,
+    ) -> Result<Box<dyn Future<Item = Response, Error = Error>>, Error> {
+        let WaitParams {
+            count,
+            timeout,
+            filter,
+        } = params;
+        let (mut matching, arrivals) = {
+            let cont = self.bins.lock()?;
+            match read_and_subscribe(&*cont, &self.events, &id)? {
+                Some((bin, arrivals)) => {
+                    let matching: Vec<models::Request> =
+                        bin.into_iter().filter(|r| filter.matches(r)).collect();
+                    (matching, arrivals)
+                }
+                None => {
+                    info!("No bin with that id: {}", id);
+                    return Ok(future_result(not_found(Response::new())));
+                }
+            }
+        };
+        if matching.len() >= count {
+            return Ok(future_result(write_json(&matching, Response::new())));
+        }
+        debug!(
+            "Waiting up to {:?} for {} more requests in bin with id: {}",
+            timeout,
+            count - matching.len(),
+            id
+        );
+        let needed = (count - matching.len()) as u64;
+        let arrived = arrivals
+            .map(|event| event.request)
+            .filter(move |r| filter.matches(r))
+            .take(needed)
+            .collect()
+            .map_err(|_| Error::UnforeseenError);
+        let timed_out = Timeout::new(timeout, &self.handle)?.map_err(Error::from);
+        Ok(Box::new(
+            arrived
+                .select2(timed_out)
+                .map_err(|e| match e {
+                    Either::A((e, _)) | Either::B((e, _)) => e,
+                })
+                .and_then(move |first| match first {
+                    Either::A((arrived, _)) => {
+                        matching.extend(arrived);
+                        if matching.len() >= count {
+                            write_json(&matching, Response::new())
+                        } else {
+                            // The stream of arrivals only ends early if the bin is deleted.
+                            info!("Bin with id {} was deleted while waiting on it", id);
+                            not_found(Response::new())
+                        }
+                    }
+                    Either::B(_) => {
+                        info!("Timed out waiting on bin with id: {}", id);
+                        timeout_json(format!(
+                            "Timed out waiting for {} matching requests",
+                            count
+                        ))
+                    }
+                }),
+        ))
+    }
+
+    fn find_bin_config(&self, path: &str) -> Result<Response, Error> {
+        let res = Response::new();
+        if let Some(id) = self.extract_id_from_bin_config_path(path) {
+            debug!("Trying to find config for a bin with id: {}", id);
+            let cont = self.bins.lock()?;
+            match cont.get_bin_config(&id)? {
+                Some(ref config) => {
+                    info!("Retrieved bin config: {:?}", config);
+                    write_json(config, res)
                 }
                 None => {
                     info!("No bin with that id: {}", id);
@@ -227,28 +782,239 @@ where
         }
     }
 
-    fn insert_request(&self, req: Request) -> Box<future::Future<Item = Response, Error = Error>> {
-        if let Some(id) = self.extract_id_from_header(req.headers()) {
-            let now = time::get_time();
-            debug!("Insert time: {:?}", now);
-            let now_millis = (now.sec as i64 * 1000) + (now.nsec as i64 / 1000 / 1000);
-            debug!("Insert time in Epoch millis: {:?}", now_millis);
+    fn find_scenario_states(&self, path: &str) -> Result<Response, Error> {
+        let res = Response::new();
+        if let Some(id) = self.extract_id_from_bin_scenarios_path(path) {
+            debug!("Trying to find scenario states for a bin with id: {}", id);
+            let cont = self.bins.lock()?;
+            match cont.get_bin_config(&id)? {
+                Some(ref config) => {
+                    let states = self.scenarios.current(&id, config);
+                    info!("Retrieved scenario states: {:?}", states);
+                    write_json(&states, res)
+                }
+                None => {
+                    info!("No bin with that id: {}", id);
+                    not_found(res)
+                }
+            }
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Err(Error::UnforeseenError)
+        }
+    }
+
+    /// Puts a bin's scenarios back in their initial states, responding with those states.
+    fn reset_scenario_states(&self, path: &str) -> Result<Response, Error> {
+        let res = Response::new();
+        if let Some(id) = self.extract_id_from_bin_scenarios_path(path) {
+            debug!("Trying to reset scenario states for a bin with id: {}", id);
+            let cont = self.bins.lock()?;
+            match cont.get_bin_config(&id)? {
+                Some(ref config) => {
+                    self.scenarios.reset(&id);
+                    info!("Reset scenario states of bin with id: {}", id);
+                    write_json(&self.scenarios.current(&id, config), res)
+                }
+                None => {
+                    info!("No bin with that id: {}", id);
+                    not_found(res)
+                }
+            }
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Err(Error::UnforeseenError)
+        }
+    }
+
+    fn update_bin_config(
+        &self,
+        path: &str,
+        req: Request,
+    ) -> Box<dyn Future<Item = Response, Error = Error>> {
+        if let Some(id) = self.extract_id_from_bin_config_path(path) {
             let bins = self.bins.clone();
-            let f = build_models_request(now_millis, req).and_then(move |req_model| {
-                let inner_bins = bins.clone();
-                let mut cont = inner_bins.lock()?;
+            let scenarios = self.scenarios.clone();
+            Box::new(read_to_bytes(req).and_then(move |body| {
                 let res = Response::new();
-                match cont.insert_request(&id, req_model) {
-                    InsertRequestStatus::Ok => {
-                        info!("Successfully inserted a request into bin with id: {}", id);
-                        ok(res)
+                let config: BinConfig = match serde_json::from_slice(&body) {
+                    Ok(config) => config,
+                    Err(e) => return bad_request_json(format!("Invalid bin config: {}", e)),
+                };
+                if let Err(e) = config.validate() {
+                    return bad_request_json(e);
+                }
+                let mut cont = bins.lock()?;
+                match cont.set_bin_config(&id, config.clone())? {
+                    SetBinConfigStatus::Ok => {
+                        info!("Updated config of bin with id: {} to {:?}", id, config);
+                        scenarios.reset(&id);
+                        write_json(&config, res)
                     }
-                    _ => {
+                    SetBinConfigStatus::NoSuchBin => {
                         info!("No bin with that id: {}", id);
                         not_found(res)
                     }
                 }
-            });
+            }))
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Box::new(future::err(Error::UnforeseenError))
+        }
+    }
+
+    /// Checks an Expectation in the request body against a bin's requests, responding with the
+    /// Verification whether or not it was met.
+    fn verify_requests(
+        &self,
+        path: &str,
+        req: Request,
+    ) -> Box<dyn Future<Item = Response, Error = Error>> {
+        if let Some(id) = self.extract_id_from_bin_verify_path(path) {
+            let bins = self.bins.clone();
+            Box::new(read_to_bytes(req).and_then(move |body| {
+                let res = Response::new();
+                let expectation: Expectation = match serde_json::from_slice(&body) {
+                    Ok(expectation) => expectation,
+                    Err(e) => return bad_request_json(format!("Invalid expectation: {}", e)),
+                };
+                if let Err(e) = expectation.validate() {
+                    return bad_request_json(e);
+                }
+                let cont = bins.lock()?;
+                match cont.get_bin(&id)? {
+                    Some(requests) => {
+                        let verification = expectation.verify(&requests);
+                        info!(
+                            "Verified bin with id: {}: {}",
+                            id,
+                            verification.message
+                        );
+                        write_json(&verification, res)
+                    }
+                    None => {
+                        info!("No bin with that id: {}", id);
+                        not_found(res)
+                    }
+                }
+            }))
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Box::new(future::err(Error::UnforeseenError))
+        }
+    }
+
+    fn replay_requests(
+        &self,
+        path: &str,
+        req: Request,
+    ) -> Box<dyn Future<Item = Response, Error = Error>> {
+        if let Some(id) = self.extract_id_from_bin_replay_path(path) {
+            let bins = self.bins.clone();
+            let handle = self.handle.clone();
+            Box::new(read_to_bytes(req).and_then(move |body| {
+                let res = Response::new();
+                let replay: Replay = match serde_json::from_slice(&body) {
+                    Ok(replay) => replay,
+                    Err(e) => {
+                        return Either::A(future::result(
+                            bad_request_json(format!("Invalid replay: {}", e)),
+                        ))
+                    }
+                };
+                if let Err(e) = replay.validate() {
+                    return Either::A(future::result(bad_request_json(e)));
+                }
+                let requests = match bins.lock().map_err(Error::from).and_then(|cont| {
+                    cont.get_bin(&id)
+                }) {
+                    Ok(Some(requests)) => requests,
+                    Ok(None) => {
+                        info!("No bin with that id: {}", id);
+                        return Either::A(future::result(not_found(res)));
+                    }
+                    Err(e) => return Either::A(future::err(e)),
+                };
+                info!("Replaying requests of bin with id: {} to {}", id, replay.target);
+                Either::B(replay::replay(requests, &replay, &handle).and_then(
+                    move |outcomes| write_json(&outcomes, res),
+                ))
+            }))
+        } else {
+            // this methods should not be invoked if extraction isn't successful
+            Box::new(future::err(Error::UnforeseenError))
+        }
+    }
+
+    fn insert_request(&self, req: Request) -> Box<future::Future<Item = Response, Error = Error>> {
+        if let Some((id, path)) = self.find_target_bin(&req) {
+            let now_millis = now_millis();
+            debug!("Insert time in Epoch millis: {:?}", now_millis);
+            let bins = self.bins.clone();
+            let recording_bins = self.bins.clone();
+            let handle = self.handle.clone();
+            let events = self.events.clone();
+            let scenarios = self.scenarios.clone();
+            let f = build_models_request(now_millis, req, path)
+                .and_then(move |req_model| {
+                    let inner_bins = bins.clone();
+                    let mut cont = inner_bins.lock()?;
+                    let res = Response::new();
+                    let config = cont.get_bin_config(&id)?.unwrap_or_default();
+                    match cont.insert_request(&id, req_model)? {
+                        InsertRequestStatus::Ok(captured) => {
+                            info!("Successfully inserted a request into bin with id: {}", id);
+                            events.publish(&id, &captured);
+                            // Scenarios only move on once the request has been captured
+                            let response = scenarios
+                                .respond(&id, &config, &captured)
+                                .or_else(|| config.response_for(&captured).cloned());
+                            let res: Box<dyn Future<Item = Response, Error = Error>> =
+                                match (response, config.upstream) {
+                                    (Some(response), _) => Box::new(future::result(
+                                        mock_response(&response.render(&captured), res),
+                                    )),
+                                    (None, Some(upstream)) => {
+                                        forward_to_upstream(
+                                            &id,
+                                            &captured,
+                                            &upstream,
+                                            &handle,
+                                        )
+                                    }
+                                    (None, None) => Box::new(future::result(ok(res))),
+                                };
+                            Ok((res, config.faults, Some((id, captured.id)), handle))
+                        }
+                        _ => {
+                            info!("No bin with that id: {}", id);
+                            let res: Box<dyn Future<Item = Response, Error = Error>> =
+                                Box::new(future::result(not_found(res)));
+                            Ok((res, Faults::default(), None, handle))
+                        }
+                    }
+                })
+                .and_then(move |(res, faults, captured_ids, handle)| {
+                    let slow = faults.slow_body.clone();
+                    let fault_handle = handle.clone();
+                    res.or_else(|e| match e {
+                        // The caller gets to see that the upstream failed, so that gets recorded
+                        Error::UpstreamError(_) => Ok(error_response(e)),
+                        e => Err(e),
+                    }).and_then(move |res| inject_faults(res, faults, &fault_handle))
+                        .and_then(move |res| -> Box<dyn Future<Item = Response, Error = Error>> {
+                            match captured_ids {
+                                Some((id, request_id)) => {
+                                    record_sent(res, recording_bins, id, request_id, now_millis)
+                                }
+                                None => Box::new(future::ok(res)),
+                            }
+                        })
+                        .map(move |res| match slow {
+                            Some(slow) => slow_body(res, slow, &handle),
+                            None => res,
+                        })
+                });
             Box::new(f)
         } else {
             // this methods should not be invoked if extraction isn't successful
@@ -257,6 +1023,101 @@ where
     }
 }
 
+/// Forwards a captured request to a bin's upstream, relaying whatever it responds with back.
+fn forward_to_upstream(
+    id: &Id,
+    captured: &models::Request,
+    upstream: &str,
+    handle: &Handle,
+) -> Box<dyn Future<Item = Response, Error = Error>> {
+    let target = replay::target_url(upstream, captured);
+    let upstream_req = match replay::build_request(captured, &target) {
+        Ok(upstream_req) => upstream_req,
+        Err(e) => return Box::new(future::err(Error::UpstreamError(e))),
+    };
+    debug!("Forwarding request {} of bin {} to {}", captured.id, id, target);
+    let f = hyper::Client::new(handle)
+        .request(upstream_req)
+        .and_then(|upstream_res| {
+            let status = upstream_res.status();
+            let headers = upstream_res.headers().clone();
+            upstream_res.body().concat2().map(move |body| {
+                (status, headers, body.to_vec())
+            })
+        })
+        .map_err(Error::UpstreamError)
+        .map(|(status, headers, body)| {
+            let mut res = Response::new().with_status(status);
+            for header in headers.iter() {
+                if replay::is_forwardable_header(header.name()) {
+                    res.headers_mut().append_raw(
+                        header.name().to_owned(),
+                        header.value_string(),
+                    );
+                }
+            }
+            res.headers_mut().set(ContentLength(body.len() as u64));
+            res.with_body(body)
+        });
+    Box::new(f)
+}
+
+/// Records the response about to be sent for a captured request alongside it, with the
+/// number of milliseconds since the request was received.
+fn record_sent<T>(
+    res: Response,
+    bins: Arc<Mutex<T>>,
+    id: Id,
+    request_id: Id,
+    received_at: i64,
+) -> Box<dyn Future<Item = Response, Error = Error>>
+where
+    T: Bins + 'static,
+{
+    let status = res.status();
+    let headers = res.headers().clone();
+    let f = res.body()
+        .concat2()
+        .map_err(|_| Error::HyperError)
+        .and_then(move |body| {
+            let body = body.to_vec();
+            let recorded = RecordedResponse {
+                status: status.as_u16(),
+                headers: headers_map(&headers),
+                body: if body.is_empty() {
+                    None
+                } else {
+                    Some(models::Body::new(body.clone()))
+                },
+                latency_ms: (now_millis() - received_at).max(0) as u64,
+            };
+            match bins.lock()?.record_response(&id, &request_id, recorded)? {
+                RecordResponseStatus::Ok => debug!("Recorded the response to request {}", request_id),
+                RecordResponseStatus::NoSuchRequest => {
+                    info!("Request {} went away before its response was sent", request_id)
+                }
+            }
+            Ok(
+                Response::new()
+                    .with_status(status)
+                    .with_headers(headers)
+                    .with_body(body),
+            )
+        });
+    Box::new(f)
+}
+
+/// Collects Headers into a map of header names to their values.
+fn headers_map(headers: &Headers) -> HashMap<String, Vec<String>> {
+    let mut map: HashMap<String, Vec<String>> = HashMap::new();
+    for header in headers.iter() {
+        map.entry(header.name().to_owned()).or_default().push(
+            header.value_string(),
+        );
+    }
+    map
+}
+
 fn write_json<T: Serialize>(t: &T, mut res: Response) -> Result<Response, Error> {
     let encoded: String = serde_json::ser::to_string_pretty(t)?;
     res.headers_mut().set(ContentLength(encoded.len() as u64));
@@ -267,6 +1128,301 @@ fn write_json<T: Serialize>(t: &T, mut res: Response) -> Result<Response, Error>
     Ok(res)
 }
 
+/// JSON body sent back when handling a request fails, over HTTP or the WebSocket feed.
+#[derive(Serialize)]
+pub(crate) struct ErrorBody {
+    pub error: &'static str,
+    pub message: String,
+}
+
+/// Turns a failure into a 5xx JSON response. Storage failures get a 503 so that clients
+/// know it may be worth retrying, failures to reach a bin's upstream get a 502, and anything
+/// else is a plain 500.
+fn error_response(e: Error) -> Response {
+    let (status, body) = match e {
+        Error::StorageError(_) => {
+            error!("Storage failure: {}", e);
+            (
+                StatusCode::ServiceUnavailable,
+                ErrorBody {
+                    error: "storage_error",
+                    message: e.to_string(),
+                },
+            )
+        }
+        Error::UpstreamError(_) => {
+            warn!("Upstream failure: {}", e);
+            (
+                StatusCode::BadGateway,
+                ErrorBody {
+                    error: "upstream_error",
+                    message: e.to_string(),
+                },
+            )
+        }
+        _ => {
+            error!("Something really messed up bad: {:?}", e);
+            (
+                StatusCode::InternalServerError,
+                ErrorBody {
+                    error: "internal_error",
+                    message: e.to_string(),
+                },
+            )
+        }
+    };
+    let mut res = Response::new();
+    res.set_status(status);
+    match write_json(&body, res) {
+        Ok(res) => res,
+        Err(_) => Response::new().with_status(status),
+    }
+}
+
+fn bad_request_json(message: String) -> Result<Response, Error> {
+    let mut res = Response::new();
+    res.set_status(StatusCode::BadRequest);
+    write_json(
+        &ErrorBody {
+            error: "bad_request",
+            message,
+        },
+        res,
+    )
+}
+
+/// Fills in a Response according to a bin's MockResponse.
+fn mock_response(mock: &MockResponse, mut res: Response) -> Result<Response, Error> {
+    // Statuses are validated when configuring, but the config may come from elsewhere.
+    res.set_status(StatusCode::try_from(mock.status).unwrap_or(StatusCode::Ok));
+    for (name, value) in &mock.headers {
+        res.headers_mut().set_raw(name.to_owned(), value.to_owned());
+    }
+    if let Some(ref body) = mock.body {
+        if !res.headers().has::<ContentLength>() {
+            res.headers_mut().set(ContentLength(body.bytes().len() as u64));
+        }
+        res.set_body(body.bytes().to_vec());
+    }
+    Ok(res)
+}
+
+/// Applies a bin's Faults, apart from any slow body, to the response for a request it
+/// captured.
+fn inject_faults(
+    res: Response,
+    faults: Faults,
+    handle: &Handle,
+) -> Box<dyn Future<Item = Response, Error = Error>> {
+    let mut rng = rand::thread_rng();
+    let delay_ms = if faults.jitter_ms > 0 {
+        faults.delay_ms.saturating_add(
+            rng.gen_range(0, faults.jitter_ms.saturating_add(1)),
+        )
+    } else {
+        faults.delay_ms
+    };
+    let outcome = if roll(&mut rng, faults.drop_percent) {
+        info!("Injecting a dropped connection");
+        Err(Error::ConnectionDropped)
+    } else if roll(&mut rng, faults.error_percent) {
+        let status = faults.error_status.unwrap_or(500);
+        info!("Injecting an error response with status {}", status);
+        Ok(Response::new().with_status(
+            StatusCode::try_from(status).unwrap_or(StatusCode::InternalServerError),
+        ))
+    } else {
+        Ok(res)
+    };
+    if delay_ms == 0 {
+        Box::new(future::result(outcome))
+    } else {
+        debug!("Delaying response by {}ms", delay_ms);
+        match Timeout::new(Duration::from_millis(delay_ms), handle) {
+            Ok(timeout) => Box::new(timeout.map_err(Error::from).and_then(move |_| outcome)),
+            Err(e) => Box::new(future::err(Error::from(e))),
+        }
+    }
+}
+
+/// Whether something that should happen `percent`% of the time happens this time.
+fn roll<R: Rng>(rng: &mut R, percent: u8) -> bool {
+    percent > 0 && rng.gen_range(0, 100) < percent
+}
+
+/// Swaps a Response's body for one that sends the same bytes, but in chunks with a pause
+/// before each.
+fn slow_body(res: Response, slow: SlowBody, handle: &Handle) -> Response {
+    let slowed = Response::new()
+        .with_status(res.status())
+        .with_headers(res.headers().clone());
+    let (tx, body) = hyper::Body::pair();
+    let delay = Duration::from_millis(slow.chunk_delay_ms);
+    let timer_handle = handle.clone();
+    handle.spawn(
+        res.body()
+            .concat2()
+            .map_err(|e| debug!("Could not read body to slow down: {}", e))
+            .and_then(move |bytes| {
+                let chunks: Vec<Vec<u8>> = bytes
+                    .chunks(slow.chunk_size)
+                    .map(|chunk| chunk.to_vec())
+                    .collect();
+                stream::iter_ok(chunks)
+                    .fold(tx, move |tx, chunk| {
+                        future::result(Timeout::new(delay, &timer_handle))
+                            .flatten()
+                            .map_err(|e| debug!("Slow body timer failed: {}", e))
+                            .and_then(move |_| {
+                                tx.send(Ok(chunk.into())).map_err(
+                                    |_| debug!("Client went away while sending a slow body"),
+                                )
+                            })
+                    })
+                    .map(|_| ())
+            }),
+    );
+    slowed.with_body(body)
+}
+
+/// Formats a Server-Sent Event carrying the given data as JSON.
+fn sse_event<T: Serialize>(event: &str, data: &T) -> Result<String, ()> {
+    match serde_json::to_string(data) {
+        Ok(json) => Ok(format!("event: {}\ndata: {}\n\n", event, json)),
+        Err(e) => {
+            error!("Could not encode {} event: {}", event, e);
+            Err(())
+        }
+    }
+}
+
+fn timeout_json(message: String) -> Result<Response, Error> {
+    let mut res = Response::new();
+    res.set_status(StatusCode::RequestTimeout);
+    write_json(
+        &ErrorBody {
+            error: "timeout",
+            message,
+        },
+        res,
+    )
+}
+
+/// Parameters for paging through a bin's requests.
+struct PageParams {
+    /// How many requests to answer with at most.
+    limit: Option<usize>,
+    /// How many matching requests to skip.
+    offset: usize,
+    order: Order,
+    /// Where the previous page left off, as given in its `X-Next-Cursor` header.
+    cursor: Option<usize>,
+}
+
+impl PageParams {
+    /// Parses a query string of the form `limit=N&offset=N&order=asc|desc&cursor=CURSOR`,
+    /// where everything is optional.
+    fn parse(query: &str) -> Result<PageParams, String> {
+        let mut params = PageParams {
+            limit: None,
+            offset: 0,
+            order: Order::Asc,
+            cursor: None,
+        };
+        for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+            match &*k {
+                "limit" => {
+                    params.limit = match v.parse() {
+                        Ok(limit) if limit > 0 => Some(limit),
+                        _ => return Err(format!("Invalid limit: {}", v)),
+                    }
+                }
+                "offset" => {
+                    params.offset = v.parse().map_err(|_| format!("Invalid offset: {}", v))?
+                }
+                "order" => {
+                    params.order = match &*v {
+                        "asc" => Order::Asc,
+                        "desc" => Order::Desc,
+                        _ => return Err(format!("Invalid order: {}", v)),
+                    }
+                }
+                "cursor" => {
+                    params.cursor = Some(v.parse().map_err(|_| format!("Invalid cursor: {}", v))?)
+                }
+                _ => (),
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// Builds the URI of the page after the current one, which keeps the current parameters but
+/// replaces any offset with the given cursor.
+fn next_page_uri(path: &str, query: Option<&str>, cursor: usize) -> String {
+    let mut next = form_urlencoded::Serializer::new(String::new());
+    for (k, v) in form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        if k != "offset" && k != "cursor" {
+            next.append_pair(&k, &v);
+        }
+    }
+    next.append_pair("cursor", &cursor.to_string());
+    format!("{}?{}", path, next.finish())
+}
+
+/// Parameters for waiting on a bin's requests.
+struct WaitParams {
+    /// How many matching requests to wait for.
+    count: usize,
+    /// How long to wait for them.
+    timeout: Duration,
+    /// Which requests count.
+    filter: RequestFilter,
+}
+
+/// Default number of seconds to wait for requests.
+const DEFAULT_WAIT_SECS: u64 = 30;
+
+/// Longest number of seconds a client can ask to wait for requests.
+const MAX_WAIT_SECS: u64 = 300;
+
+impl WaitParams {
+    /// Parses a query string of the form `count=N&timeout=SECONDS`, along with the parameters
+    /// of a RequestFilter, where everything is optional.
+    fn parse(query: Option<&str>) -> Result<WaitParams, String> {
+        let query = query.unwrap_or("");
+        let mut params = WaitParams {
+            count: 1,
+            timeout: Duration::from_secs(DEFAULT_WAIT_SECS),
+            filter: RequestFilter::from_query(query)?,
+        };
+        for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+            match &*k {
+                "count" => {
+                    params.count = match v.parse() {
+                        Ok(count) if count > 0 => count,
+                        _ => return Err(format!("Invalid count: {}", v)),
+                    }
+                }
+                "timeout" => {
+                    params.timeout = match v.parse() {
+                        Ok(secs) if secs <= MAX_WAIT_SECS => Duration::from_secs(secs),
+                        _ => {
+                            return Err(format!(
+                                "Invalid timeout, must be at most {} seconds: {}",
+                                MAX_WAIT_SECS,
+                                v
+                            ))
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(params)
+    }
+}
+
 fn not_found(mut res: Response) -> Result<Response, Error> {
     res.set_status(StatusCode::NotFound);
     Ok(res)
@@ -282,22 +1438,20 @@ fn ok(mut res: Response) -> Result<Response, Error> {
     Ok(res)
 }
 
+/// Turns a Hyper Request into a models::Request, recording the given path (which should include
+/// any query string) instead of the requested one if there is one.
 fn build_models_request(
     req_time: i64,
     req: Request,
+    path: Option<String>,
 ) -> Box<Future<Item = models::Request, Error = errors::Error>> {
     let req_headers: Headers = req.headers().clone(); // to escape immutable req borrow..
     let content_length = req_headers.get::<ContentLength>().map(|l| l.0);
     let content_type = req_headers.get::<ContentType>().map(|t| t.0.clone());
     let method = req.method().to_string();
-    let path = format!("{}", req.uri());
+    let path = path.unwrap_or_else(|| format!("{}", req.uri()));
 
-    let mut headers: HashMap<String, Vec<String>> = HashMap::new();
-    for header in req_headers.iter() {
-        let k = header.name();
-        let v = header.value_string();
-        headers.entry(k.to_owned()).or_insert(vec![]).push(v);
-    }
+    let headers = headers_map(&req_headers);
 
     // our req is at this point guaranteed to be an AbsolutePath by the time it comes here.
     let parsed_url: Url = match Url::parse(&*format!("http://b.com{}", path)) {
@@ -312,11 +1466,16 @@ fn build_models_request(
         );
     }
 
-    let future_body: Box<Future<Item = Option<String>, Error = errors::Error>> =
-        read_to_string(req);
+    let future_body = read_to_bytes(req).map(|b| if b.is_empty() {
+        None
+    } else {
+        Some(models::Body::new(b))
+    });
 
     Box::new(future_body.map(move |body| {
         models::Request {
+            id: Id::random(),
+            seq: 0,
             content_length: content_length,
             content_type: content_type,
             time: req_time,
@@ -325,6 +1484,7 @@ fn build_models_request(
             body: body,
             headers: headers,
             query_string: query_map,
+            response: None,
         }
     }))
 }
@@ -334,11 +1494,17 @@ where
     T: Bins + Send + 'static,
 {
     pub fn new(port: usize, bins: T) -> BinsServer<T> {
+        BinsServer::with_config(port, bins, ServerConfig::default())
+    }
+
+    /// Returns a BinsServer that uses the given server-wide settings.
+    pub fn with_config(port: usize, bins: T, config: ServerConfig) -> BinsServer<T> {
         let address = format!("0.0.0.0:{}", port);
         BinsServer {
             address: address,
             port: port,
             storage: Arc::new(Mutex::new(bins)),
+            config,
         }
     }
 
@@ -353,29 +1519,129 @@ where
         F: future::Future<Item = (), Error = ()>,
     {
         let addr = self.address.parse()?;
+        let mut core = Core::new()?;
+        let handle = core.handle();
         let storage = self.storage.clone();
-        let server = Http::new().bind(&addr, move || {
+        let config = Arc::new(self.config.clone());
+        let worker_handle = handle.clone();
+        let events = BinEvents::new();
+        let reaper_events = events.clone();
+        let feed_events = events.clone();
+        let scenarios = ScenarioStates::new();
+        let reaper_scenarios = scenarios.clone();
+        let bin_path_regexp = bin_path_regexp(&self.config.bin_path_prefix)?;
+        let serve = Http::new().serve_addr_handle(&addr, &handle, move || {
             Ok(Worker {
                 id_extractor: IdExtractor::new(),
                 bin_summary_path_regexp: BIN_SUMMARY_PATH_REGEXP.clone(),
                 bin_requests_path_regexp: BIN_REQUESTS_PATH_REGEXP.clone(),
+                bin_request_path_regexp: BIN_REQUEST_PATH_REGEXP.clone(),
+                bin_wait_path_regexp: BIN_WAIT_PATH_REGEXP.clone(),
+                bin_stream_path_regexp: BIN_STREAM_PATH_REGEXP.clone(),
+                bin_verify_path_regexp: BIN_VERIFY_PATH_REGEXP.clone(),
+                bin_replay_path_regexp: BIN_REPLAY_PATH_REGEXP.clone(),
+                bin_scenarios_path_regexp: BIN_SCENARIOS_PATH_REGEXP.clone(),
+                bin_config_path_regexp: BIN_CONFIG_PATH_REGEXP.clone(),
+                bin_path_regexp: bin_path_regexp.clone(),
                 bins: storage.clone(),
+                config: config.clone(),
+                handle: worker_handle.clone(),
+                events: events.clone(),
+                scenarios: scenarios.clone(),
             })
         })?;
-        Ok(server.run_until(shutdown_signal)?)
+        let conn_handle = handle.clone();
+        handle.spawn(
+            serve
+                .for_each(move |conn| {
+                    conn_handle.spawn(conn.map(|_| ()).map_err(
+                        |e| debug!("Connection error: {}", e),
+                    ));
+                    Ok(())
+                })
+                .map_err(|e| error!("Server error: {}", e)),
+        );
+        handle.spawn(reap_expired_bins(
+            self.storage.clone(),
+            reaper_events,
+            reaper_scenarios,
+            self.config.reap_interval,
+            &handle,
+        )?);
+        if let Some(port) = self.config.websocket_port {
+            let feed_addr = format!("0.0.0.0:{}", port).parse()?;
+            handle.spawn(serve_feed(
+                &feed_addr,
+                self.storage.clone(),
+                feed_events,
+                &handle,
+            )?);
+        }
+        core.run(shutdown_signal).map_err(|_| Error::UnforeseenError)
     }
 }
 
-/// Consumes the body and reads it into a String.
-fn read_to_string(req: Request) -> Box<Future<Item = Option<String>, Error = Error>> {
-    Box::new(read_to_bytes(req).and_then(|b| {
-        let s = String::from_utf8(b).map_err(|_| Error::FromUtf8Error);
-        match s {
-            Ok(ref s) if s.len() == 0 => Ok(None),
-            Ok(s) => Ok(Some(s)),
-            Err(e) => Err(e),
-        }
-    }))
+/// Builds the Regex for paths that route requests to a bin, capturing the Id and the rest of
+/// the path.
+fn bin_path_regexp(prefix: &str) -> Result<Regex, Error> {
+    let prefix = prefix.trim_matches('/');
+    let prefix = if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", regex::escape(prefix))
+    };
+    Ok(Regex::new(
+        &format!(r"^{}/({})(/.*)?$", prefix, BIN_ID_PATTERN),
+    )?)
+}
+
+/// Reads the bin with the given Id and subscribes to the requests it captures from now on, if
+/// there is such a bin. Meant to be called while holding the lock on the bins, so that nothing
+/// gets captured in between reading the bin and listening for more.
+fn read_and_subscribe<T>(
+    bins: &T,
+    events: &BinEvents,
+    id: &Id,
+) -> Result<Option<(Bin, UnboundedReceiver<BinEvent>)>, Error>
+where
+    T: Bins,
+{
+    Ok(bins.get_bin(id)?.map(|bin| (bin, events.subscribe(id))))
+}
+
+/// Periodically deletes bins whose TTL has passed.
+fn reap_expired_bins<T>(
+    bins: Arc<Mutex<T>>,
+    events: BinEvents,
+    scenarios: ScenarioStates,
+    every: Duration,
+    handle: &Handle,
+) -> Result<Box<dyn Future<Item = (), Error = ()>>, Error>
+where
+    T: Bins + 'static,
+{
+    let interval = Interval::new(every, handle)?;
+    Ok(Box::new(
+        interval
+            .map_err(|e| error!("Reaper timer failed: {}", e))
+            .for_each(move |_| {
+                let mut cont = bins.lock().map_err(
+                    |_| error!("Poisoned lock, stopping the reaper"),
+                )?;
+                match cont.delete_expired_bins(now_millis()) {
+                    Ok(ref ids) if ids.is_empty() => (),
+                    Ok(ids) => {
+                        info!("Deleted expired bins: {:?}", ids);
+                        for id in &ids {
+                            events.close(id);
+                            scenarios.reset(id);
+                        }
+                    }
+                    Err(e) => error!("Failed to delete expired bins: {}", e),
+                }
+                Ok(())
+            }),
+    ))
 }
 
 /// Consumes a request, returning the body as a vector of bytes
@@ -393,3 +1659,25 @@ fn read_to_bytes(req: Request) -> Box<Future<Item = Vec<u8>, Error = Error>> {
                 Ok::<_, Error>(acc)
             }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_and_subscribe() {
+        let mut bins = InMemoryBins::new();
+        let events = BinEvents::new();
+        let bin = bins.create_bin().unwrap();
+        let missing = Id::random();
+
+        assert!(read_and_subscribe(&bins, &events, &missing).unwrap().is_none());
+        assert!(!events.has_subscribers(&missing));
+
+        let (requests, _arrivals) = read_and_subscribe(&bins, &events, &bin.id)
+            .unwrap()
+            .unwrap();
+        assert!(requests.is_empty());
+        assert!(events.has_subscribers(&bin.id));
+    }
+}
@@ -0,0 +1,595 @@
+//! An append-only, file-backed implementation of Bins.
+//!
+//! Every mutation is written as a single JSON line to a log file inside a data directory
+//! before being applied to an in-memory copy of the bins. On startup the log is replayed
+//! to rebuild that state, so bins and their requests survive process restarts.
+//!
+//! Once the log holds a lot more entries than the bins it describes, e.g. for requests that
+//! have since been evicted or deleted, it is compacted: rewritten as a snapshot of each bin.
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use uuid::Uuid;
+
+use models::*;
+use errors::Error;
+use storage::{Bin, Bins, ClearBinStatus, DeleteBinStatus, DeleteRequestStatus, InMemoryBin,
+              InMemoryBins, InsertRequestStatus, RecordResponseStatus, RequestRange,
+              SetBinConfigStatus};
+
+/// Name of the log file kept inside the data directory.
+pub const LOG_FILE_NAME: &str = "bins.jsonl";
+
+/// Name of the file a compacted log is written to before it replaces the log.
+const COMPACTED_LOG_FILE_NAME: &str = "bins.jsonl.compacting";
+
+/// The log is never compacted with fewer entries than this in it.
+const MIN_ENTRIES_TO_COMPACT: usize = 1000;
+
+/// A single line in the append-only log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum LogEntry {
+    CreateBin {
+        id: Id,
+        #[serde(default)]
+        options: BinOptions,
+        #[serde(default)]
+        created_at: i64,
+    },
+    DeleteBin { id: Id },
+    SetBinConfig { id: Id, config: BinConfig },
+    InsertRequest { id: Id, request: Box<Request> },
+    DeleteRequest { id: Id, request_id: Id },
+    ClearBin { id: Id },
+    RecordResponse {
+        id: Id,
+        request_id: Id,
+        response: RecordedResponse,
+    },
+    /// A bin as it was when the log was compacted, replacing all the entries about it before.
+    BinSnapshot {
+        id: Id,
+        options: BinOptions,
+        created_at: i64,
+        config: BinConfig,
+        total_request_count: usize,
+        requests: Vec<Request>,
+    },
+}
+
+/// A Bins implementation that persists to an append-only JSON-lines log on disk.
+///
+/// Reads are served from memory; the log is only read when opening.
+#[derive(Debug)]
+pub struct FileBins {
+    path: PathBuf,
+    log: File,
+    bins: InMemoryBins,
+    /// Number of entries in the log.
+    entries: usize,
+    /// Number of entries at which the log gets compacted.
+    compact_at: usize,
+}
+
+impl FileBins {
+    /// Opens (creating if necessary) a FileBins in the given data directory, replaying
+    /// any existing log to rebuild state.
+    pub fn open<P: AsRef<Path>>(data_dir: P) -> Result<FileBins, Error> {
+        fs::create_dir_all(data_dir.as_ref())?;
+        let path = data_dir.as_ref().join(LOG_FILE_NAME);
+        let (bins, valid_len, entries) = if path.exists() {
+            replay(&path)?
+        } else {
+            (InMemoryBins::new(), 0, 0)
+        };
+        let log = OpenOptions::new().create(true).append(true).open(&path)?;
+        // Drop any truncated trailing entry so new entries start on a fresh line.
+        if log.metadata()?.len() > valid_len {
+            log.set_len(valid_len)?;
+        }
+        info!(
+            "Opened bins log at {} with {} bins",
+            path.display(),
+            bins.bins.len()
+        );
+        let mut file_bins = FileBins {
+            path,
+            log,
+            bins,
+            entries,
+            compact_at: 0,
+        };
+        file_bins.compact_at = file_bins.next_compaction();
+        if file_bins.entries >= file_bins.compact_at {
+            file_bins.compact()?;
+        }
+        Ok(file_bins)
+    }
+
+    /// Path to the log file backing this FileBins.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&mut self, entry: &LogEntry) -> Result<(), Error> {
+        // Every change before this one has been applied in memory, so the snapshot is complete.
+        if self.entries >= self.compact_at {
+            if let Err(e) = self.compact() {
+                warn!("Failed to compact {}: {}", self.path.display(), e);
+                self.compact_at = self.entries + MIN_ENTRIES_TO_COMPACT;
+            }
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        // One write per entry so that a crash can at most leave a truncated last line.
+        self.log.write_all(&line).map_err(Error::storage)?;
+        self.log.flush().map_err(Error::storage)?;
+        self.entries += 1;
+        Ok(())
+    }
+
+    /// Number of entries at which to compact the log next: once it has twice as many as it
+    /// would take to log the current bins and their requests one by one.
+    fn next_compaction(&self) -> usize {
+        let live: usize = self.bins.bins.values().map(|b| 1 + b.requests.len()).sum();
+        MIN_ENTRIES_TO_COMPACT.max(live.saturating_mul(2))
+    }
+
+    /// Replaces the log with a snapshot of each bin. The snapshot is written to another file
+    /// first and then renamed over the log, so a crash leaves one or the other in place.
+    fn compact(&mut self) -> Result<(), Error> {
+        let compacted_path = self.path.with_file_name(COMPACTED_LOG_FILE_NAME);
+        {
+            let mut compacted = BufWriter::new(File::create(&compacted_path)?);
+            for (id, bin) in &self.bins.bins {
+                let entry = LogEntry::BinSnapshot {
+                    id: id.to_owned(),
+                    options: bin.options.clone(),
+                    created_at: bin.created_at,
+                    config: bin.config.clone(),
+                    total_request_count: bin.total_request_count,
+                    requests: bin.requests.iter().cloned().collect(),
+                };
+                serde_json::to_writer(&mut compacted, &entry)?;
+                compacted.write_all(b"\n")?;
+            }
+            let compacted = compacted.into_inner().map_err(|e| e.into_error())?;
+            compacted.sync_all()?;
+        }
+        fs::rename(&compacted_path, &self.path)?;
+        self.log = OpenOptions::new().append(true).open(&self.path)?;
+        info!(
+            "Compacted {} from {} entries to {}",
+            self.path.display(),
+            self.entries,
+            self.bins.bins.len()
+        );
+        self.entries = self.bins.bins.len();
+        self.compact_at = self.next_compaction();
+        Ok(())
+    }
+}
+
+/// Rebuilds in-memory state from a log file, returning it along with the length in bytes of
+/// the log up to the end of its last complete entry and the number of entries up to there.
+fn replay(path: &Path) -> Result<(InMemoryBins, u64, usize), Error> {
+    let mut bins = InMemoryBins::new();
+    let id_extractor = IdExtractor::new();
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut valid_len: u64 = 0;
+    let mut entries = 0;
+    let mut line_num = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        line_num += 1;
+        let complete = line.last() == Some(&b'\n');
+        match serde_json::from_slice(&line) {
+            Ok(LogEntry::CreateBin {
+                id,
+                options,
+                created_at,
+            }) => {
+                bins.bins.insert(id, InMemoryBin::new(&options, created_at));
+            }
+            Ok(LogEntry::DeleteBin { id }) => {
+                bins.bins.remove(&id);
+            }
+            Ok(LogEntry::SetBinConfig { id, config }) => {
+                bins.set_bin_config(&id, config)?;
+            }
+            Ok(LogEntry::InsertRequest { id, request }) => {
+                let missing_id = request.id.value().is_empty();
+                if let InsertRequestStatus::Ok(inserted) = bins.insert_request(&id, *request)? {
+                    if missing_id {
+                        let stored = bins.bins.get_mut(&id).and_then(|b| b.requests.back_mut());
+                        if let Some(stored) = stored {
+                            stored.id = legacy_request_id(&id_extractor, &id, inserted.seq);
+                        }
+                    }
+                }
+            }
+            Ok(LogEntry::DeleteRequest { id, request_id }) => {
+                bins.delete_request(&id, &request_id)?;
+            }
+            Ok(LogEntry::ClearBin { id }) => {
+                bins.clear_bin(&id)?;
+            }
+            Ok(LogEntry::RecordResponse {
+                id,
+                request_id,
+                response,
+            }) => {
+                bins.record_response(&id, &request_id, response)?;
+            }
+            Ok(LogEntry::BinSnapshot {
+                id,
+                options,
+                created_at,
+                config,
+                total_request_count,
+                requests,
+            }) => {
+                let bin = InMemoryBin {
+                    options,
+                    config,
+                    created_at,
+                    total_request_count,
+                    requests: requests.into(),
+                };
+                bins.bins.insert(id, bin);
+            }
+            // A partially-written last line is what we expect after a crash mid-write.
+            Err(ref e) if !complete => {
+                warn!(
+                    "Ignoring truncated last line {} of {}: {}",
+                    line_num,
+                    path.display(),
+                    e
+                );
+                break;
+            }
+            Err(e) => return Err(Error::from(e)),
+        }
+        if complete {
+            valid_len += read as u64;
+        } else {
+            // Still a complete entry, so terminate it before anything else gets appended.
+            let mut log = OpenOptions::new().append(true).open(path)?;
+            log.write_all(b"\n")?;
+            valid_len += read as u64 + 1;
+        }
+        entries += 1;
+    }
+    Ok((bins, valid_len, entries))
+}
+
+/// Derives an Id for a request that was logged before requests had Ids from its bin's Id and
+/// its sequence number, so that it gets the same one every time the log is replayed (and
+/// entries logged since, e.g. deletions, keep referring to it).
+fn legacy_request_id(id_extractor: &IdExtractor, bin_id: &Id, seq: usize) -> Id {
+    let mut bytes = Uuid::parse_str(bin_id.value())
+        .map(|uuid| *uuid.as_bytes())
+        .unwrap_or([0; 16]);
+    for (byte, seq_byte) in bytes[8..].iter_mut().zip(&(seq as u64).to_be_bytes()) {
+        *byte ^= seq_byte;
+    }
+    // Keep it looking like a version 4 UUID, as Ids are expected to be
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Uuid::from_bytes(&bytes)
+        .ok()
+        .and_then(|uuid| id_extractor.parse(&uuid.to_string()))
+        .unwrap_or_else(Id::random)
+}
+
+/// Changes are only applied in memory once they have been appended to the log, so a failed
+/// write leaves both in agreement.
+impl Bins for FileBins {
+    fn create_bin_with_options(&mut self, options: &BinOptions) -> Result<BinSummary, Error> {
+        let mut id = Id::random();
+        while self.bins.bins.contains_key(&id) {
+            id = Id::random();
+        }
+        let bin = InMemoryBin::new(options, now_millis());
+        self.append(&LogEntry::CreateBin {
+            id: id.to_owned(),
+            options: options.clone(),
+            created_at: bin.created_at,
+        })?;
+        let summary = bin.summary(&id);
+        self.bins.bins.insert(id, bin);
+        Ok(summary)
+    }
+
+    fn delete_bin(&mut self, id: &Id) -> Result<DeleteBinStatus, Error> {
+        if !self.bins.bins.contains_key(id) {
+            return Ok(DeleteBinStatus::NoSuchBin);
+        }
+        self.append(&LogEntry::DeleteBin { id: id.to_owned() })?;
+        self.bins.delete_bin(id)
+    }
+
+    fn get_bin(&self, id: &Id) -> Result<Option<Bin>, Error> {
+        self.bins.get_bin(id)
+    }
+
+    fn get_bin_range(&self, id: &Id, range: &RequestRange) -> Result<Option<Bin>, Error> {
+        self.bins.get_bin_range(id, range)
+    }
+
+    fn get_request(&self, id: &Id, request_id: &Id) -> Result<Option<Request>, Error> {
+        self.bins.get_request(id, request_id)
+    }
+
+    fn delete_request(&mut self, id: &Id, request_id: &Id) -> Result<DeleteRequestStatus, Error> {
+        let found = self.bins.bins.get(id).is_some_and(|bin| {
+            bin.requests.iter().any(|r| &r.id == request_id)
+        });
+        if !found {
+            return Ok(DeleteRequestStatus::NoSuchRequest);
+        }
+        let entry = LogEntry::DeleteRequest {
+            id: id.to_owned(),
+            request_id: request_id.to_owned(),
+        };
+        self.append(&entry)?;
+        self.bins.delete_request(id, request_id)
+    }
+
+    fn record_response(
+        &mut self,
+        id: &Id,
+        request_id: &Id,
+        response: RecordedResponse,
+    ) -> Result<RecordResponseStatus, Error> {
+        let found = self.bins.bins.get(id).is_some_and(|bin| {
+            bin.requests.iter().any(|r| &r.id == request_id)
+        });
+        if !found {
+            return Ok(RecordResponseStatus::NoSuchRequest);
+        }
+        let entry = LogEntry::RecordResponse {
+            id: id.to_owned(),
+            request_id: request_id.to_owned(),
+            response,
+        };
+        self.append(&entry)?;
+        match entry {
+            LogEntry::RecordResponse { response, .. } => {
+                self.bins.record_response(id, request_id, response)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn clear_bin(&mut self, id: &Id) -> Result<ClearBinStatus, Error> {
+        if !self.bins.bins.contains_key(id) {
+            return Ok(ClearBinStatus::NoSuchBin);
+        }
+        self.append(&LogEntry::ClearBin { id: id.to_owned() })?;
+        self.bins.clear_bin(id)
+    }
+
+    fn get_bin_summary(&self, id: &Id) -> Result<Option<BinSummary>, Error> {
+        self.bins.get_bin_summary(id)
+    }
+
+    fn get_bin_summaries(&self) -> Result<HashMap<Id, BinSummary>, Error> {
+        self.bins.get_bin_summaries()
+    }
+
+    fn get_bin_config(&self, id: &Id) -> Result<Option<BinConfig>, Error> {
+        self.bins.get_bin_config(id)
+    }
+
+    fn set_bin_config(&mut self, id: &Id, config: BinConfig) -> Result<SetBinConfigStatus, Error> {
+        if !self.bins.bins.contains_key(id) {
+            return Ok(SetBinConfigStatus::NoSuchBin);
+        }
+        let entry = LogEntry::SetBinConfig {
+            id: id.to_owned(),
+            config,
+        };
+        self.append(&entry)?;
+        match entry {
+            LogEntry::SetBinConfig { config, .. } => self.bins.set_bin_config(id, config),
+            _ => unreachable!(),
+        }
+    }
+
+    fn insert_request(&mut self, id: &Id, request: Request) -> Result<InsertRequestStatus, Error> {
+        if !self.bins.bins.contains_key(id) {
+            return Ok(InsertRequestStatus::NoSuchBin);
+        }
+        let entry = LogEntry::InsertRequest {
+            id: id.to_owned(),
+            request: Box::new(request),
+        };
+        self.append(&entry)?;
+        match entry {
+            LogEntry::InsertRequest { request, .. } => self.bins.insert_request(id, *request),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_data_dir() -> PathBuf {
+        env::temp_dir().join(format!("rusqbin-test-{}", Id::random()))
+    }
+
+    bins_test_suite!(FileBins::open(temp_data_dir()).unwrap());
+
+    #[test]
+    fn test_file_bins_restores_state_on_reopen() {
+        let dir = temp_data_dir();
+        let (first, second, third) = (
+            sample_request("/first"),
+            sample_request("/second"),
+            sample_request("/third"),
+        );
+        let (kept, deleted, cleared) = {
+            let mut bins = FileBins::open(&dir).unwrap();
+            let kept = bins.create_bin().unwrap();
+            let deleted = bins.create_bin().unwrap();
+            let cleared = bins.create_bin().unwrap();
+            bins.insert_request(&kept.id, first.clone()).unwrap();
+            bins.insert_request(&kept.id, second.clone()).unwrap();
+            bins.insert_request(&kept.id, third.clone()).unwrap();
+            bins.delete_request(&kept.id, &second.id).unwrap();
+            bins.record_response(&kept.id, &third.id, sample_response()).unwrap();
+            bins.insert_request(&deleted.id, sample_request("/gone")).unwrap();
+            bins.delete_bin(&deleted.id).unwrap();
+            bins.insert_request(&cleared.id, sample_request("/cleared")).unwrap();
+            bins.clear_bin(&cleared.id).unwrap();
+            bins.set_bin_config(&kept.id, sample_config()).unwrap();
+            (kept, deleted, cleared)
+        };
+
+        let bins = FileBins::open(&dir).unwrap();
+        assert!(bins.get_bin_summary(&deleted.id).unwrap().is_none());
+        let summary = bins.get_bin_summary(&kept.id).unwrap().unwrap();
+        assert_eq!(summary.created_at, kept.created_at);
+        assert_eq!(bins.get_bin_config(&kept.id).unwrap(), Some(sample_config()));
+        let restored = bins.get_bin(&kept.id).unwrap().unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored[0], stored(&first, 1));
+        assert_eq!(
+            restored[1],
+            Request {
+                response: Some(sample_response()),
+                ..stored(&third, 3)
+            }
+        );
+        assert!(bins.get_bin(&cleared.id).unwrap().unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_bins_gives_legacy_requests_stable_ids() {
+        let dir = temp_data_dir();
+        let bin = FileBins::open(&dir).unwrap().create_bin().unwrap();
+        {
+            // Requests logged before they had Ids
+            let mut log = OpenOptions::new()
+                .append(true)
+                .open(dir.join(LOG_FILE_NAME))
+                .unwrap();
+            for path in &["/first", "/second"] {
+                let mut request = serde_json::to_value(sample_request(path)).unwrap();
+                request.as_object_mut().unwrap().remove("id");
+                writeln!(
+                    log,
+                    r#"{{"event":"insert_request","id":"{}","request":{}}}"#,
+                    bin.id,
+                    request
+                ).unwrap();
+            }
+        }
+
+        let ids = |bins: &FileBins| -> Vec<Id> {
+            bins.get_bin(&bin.id)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|r| r.id)
+                .collect()
+        };
+        let first_ids = {
+            let mut bins = FileBins::open(&dir).unwrap();
+            let first_ids = ids(&bins);
+            assert_eq!(first_ids.len(), 2);
+            assert_ne!(first_ids[0], first_ids[1]);
+            assert!(IdExtractor::new().parse(first_ids[0].value()).is_some());
+            bins.delete_request(&bin.id, &first_ids[0]).unwrap();
+            first_ids
+        };
+
+        // Same Ids after every replay, so the deletion still applies
+        let bins = FileBins::open(&dir).unwrap();
+        assert_eq!(ids(&bins), vec![first_ids[1].clone()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_bins_compacts_log() {
+        let dir = temp_data_dir();
+        let count = MIN_ENTRIES_TO_COMPACT * 2;
+        let options = BinOptions {
+            max_requests: Some(2),
+            ..BinOptions::default()
+        };
+        let (bin, requests) = {
+            let mut bins = FileBins::open(&dir).unwrap();
+            let bin = bins.create_bin_with_options(&options).unwrap();
+            bins.set_bin_config(&bin.id, sample_config()).unwrap();
+            let requests: Vec<Request> = (0..count)
+                .map(|i| sample_request(&format!("/{}", i)))
+                .collect();
+            for request in &requests {
+                bins.insert_request(&bin.id, request.clone()).unwrap();
+            }
+            bins.record_response(&bin.id, &requests[count - 1].id, sample_response())
+                .unwrap();
+            (bin, requests)
+        };
+        let log = fs::read_to_string(dir.join(LOG_FILE_NAME)).unwrap();
+        assert!(log.lines().count() < MIN_ENTRIES_TO_COMPACT);
+        assert!(!dir.join(COMPACTED_LOG_FILE_NAME).exists());
+
+        let bins = FileBins::open(&dir).unwrap();
+        let summary = bins.get_bin_summary(&bin.id).unwrap().unwrap();
+        assert_eq!(summary.total_request_count, count);
+        assert_eq!(summary.max_requests, Some(2));
+        assert_eq!(bins.get_bin_config(&bin.id).unwrap(), Some(sample_config()));
+        assert_eq!(
+            bins.get_bin(&bin.id).unwrap().unwrap(),
+            vec![
+                stored(&requests[count - 2], count - 1),
+                Request {
+                    response: Some(sample_response()),
+                    ..stored(&requests[count - 1], count)
+                },
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_bins_ignores_truncated_last_line() {
+        let dir = temp_data_dir();
+        let bin = {
+            let mut bins = FileBins::open(&dir).unwrap();
+            let bin = bins.create_bin().unwrap();
+            bins.insert_request(&bin.id, sample_request("/ok")).unwrap();
+            bin
+        };
+        {
+            let mut log = OpenOptions::new()
+                .append(true)
+                .open(dir.join(LOG_FILE_NAME))
+                .unwrap();
+            log.write_all(b"{\"event\":\"insert_req").unwrap();
+        }
+
+        let bins = FileBins::open(&dir).unwrap();
+        assert_eq!(
+            bins.get_bin_summary(&bin.id).unwrap().unwrap().request_count,
+            1
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
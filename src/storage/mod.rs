@@ -0,0 +1,399 @@
+//! Defines a Bins trait for storing Requests as well as a default in-memory implementation
+//! of that trait for easy testing, plus file-backed and SQLite-backed implementations that
+//! survive restarts.
+use std::collections::{HashMap, VecDeque};
+
+use models::*;
+use errors::Error;
+
+#[cfg(test)]
+#[macro_use]
+pub mod test_suite;
+mod file;
+mod sqlite;
+
+pub use self::file::FileBins;
+pub use self::sqlite::SqliteBins;
+
+/// ADT for denoting status when inserting a request with a bin id.
+pub enum InsertRequestStatus {
+    /// Insert successful. Holds the request as stored, with its `seq` filled in.
+    Ok(Box<Request>),
+    /// Insert failed because no bin by that Id exists
+    NoSuchBin,
+}
+
+/// ADT For deleting a bin by id
+pub enum DeleteBinStatus {
+    /// Successfully deleted
+    Ok,
+    /// No such bin. Deletion was not carried out.
+    NoSuchBin,
+}
+
+/// ADT for deleting a single request by bin id and request id
+pub enum DeleteRequestStatus {
+    /// Successfully deleted
+    Ok,
+    /// No such bin, or no such request in it. Deletion was not carried out.
+    NoSuchRequest,
+}
+
+/// ADT for recording the response sent back for a request, by bin id and request id
+pub enum RecordResponseStatus {
+    /// Successfully recorded
+    Ok,
+    /// No such bin, or no such request in it. Nothing was recorded.
+    NoSuchRequest,
+}
+
+/// ADT for clearing the requests out of a bin by id
+pub enum ClearBinStatus {
+    /// Successfully cleared
+    Ok,
+    /// No such bin. Nothing was changed.
+    NoSuchBin,
+}
+
+/// ADT for replacing the config of a bin by id
+pub enum SetBinConfigStatus {
+    /// Successfully replaced
+    Ok,
+    /// No such bin. Nothing was changed.
+    NoSuchBin,
+}
+
+/// A Bin holds a bunch of requests. For now it's just an alias for a Vector.
+pub type Bin = Vec<Request>;
+
+/// Order in which to read a bin's requests.
+#[derive(PartialEq, Debug, Eq, Clone, Copy)]
+pub enum Order {
+    /// Oldest first
+    Asc,
+    /// Newest first
+    Desc,
+}
+
+/// Which of a bin's requests to read: starting from the one with the `from_seq` sequence
+/// number if given, skipping `offset` of them in the given `order`, then taking up to `limit`
+/// of them.
+#[derive(PartialEq, Debug, Eq, Clone, Copy)]
+pub struct RequestRange {
+    pub from_seq: Option<usize>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub order: Order,
+}
+
+impl Default for RequestRange {
+    /// Every request, oldest first.
+    fn default() -> RequestRange {
+        RequestRange {
+            from_seq: None,
+            offset: 0,
+            limit: None,
+            order: Order::Asc,
+        }
+    }
+}
+
+impl RequestRange {
+    /// Picks this range out of a whole bin's worth of requests, oldest first.
+    pub fn apply<I>(&self, requests: I) -> Bin
+    where
+        I: DoubleEndedIterator<Item = Request>,
+    {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let from_seq = self.from_seq;
+        match self.order {
+            Order::Asc => {
+                requests
+                    .filter(|r| from_seq.is_none_or(|seq| r.seq >= seq))
+                    .skip(self.offset)
+                    .take(limit)
+                    .collect()
+            }
+            Order::Desc => {
+                requests
+                    .rev()
+                    .filter(|r| from_seq.is_none_or(|seq| r.seq <= seq))
+                    .skip(self.offset)
+                    .take(limit)
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Trait for storage operations for Requests.
+///
+/// Every operation is fallible so that implementations backed by disks or databases can report
+/// failures; these should be wrapped in `Error::StorageError` (see `Error::storage`).
+pub trait Bins {
+    /// Returns a BinSummary of a newly-reated bin with default options. The Id in the summary
+    /// must be unique at the time of creation.
+    fn create_bin(&mut self) -> Result<BinSummary, Error> {
+        self.create_bin_with_options(&BinOptions::default())
+    }
+
+    /// Returns a BinSummary of a newly-created bin with the given options. The Id in the
+    /// summary must be unique at the time of creation.
+    fn create_bin_with_options(&mut self, options: &BinOptions) -> Result<BinSummary, Error>;
+
+    /// Delete a bin by Id
+    fn delete_bin(&mut self, id: &Id) -> Result<DeleteBinStatus, Error>;
+
+    /// Get a bin (not just a summary) by Id
+    ///
+    /// Returns an owned Bin so that implementations are free to load it from outside of memory.
+    fn get_bin(&self, id: &Id) -> Result<Option<Bin>, Error>;
+
+    /// Get a range of a bin's requests by Id, so that big bins can be read a bit at a time.
+    ///
+    /// The default implementation loads the whole bin, so implementations that don't keep
+    /// bins in memory should override it.
+    fn get_bin_range(&self, id: &Id, range: &RequestRange) -> Result<Option<Bin>, Error> {
+        Ok(self.get_bin(id)?.map(|bin| range.apply(bin.into_iter())))
+    }
+
+    /// Get a single request from a bin by their Ids
+    fn get_request(&self, id: &Id, request_id: &Id) -> Result<Option<Request>, Error> {
+        Ok(self.get_bin(id)?.and_then(|bin| {
+            bin.into_iter().find(|r| &r.id == request_id)
+        }))
+    }
+
+    /// Delete a single request from a bin by their Ids
+    fn delete_request(&mut self, id: &Id, request_id: &Id) -> Result<DeleteRequestStatus, Error>;
+
+    /// Record the response sent back for a single request, by the Ids of its bin and itself,
+    /// replacing any that was recorded before.
+    fn record_response(
+        &mut self,
+        id: &Id,
+        request_id: &Id,
+        response: RecordedResponse,
+    ) -> Result<RecordResponseStatus, Error>;
+
+    /// Delete all of a bin's requests by Id, keeping the bin itself along with its options and
+    /// config. Sequence numbers carry on from where they were, so they are never reused.
+    fn clear_bin(&mut self, id: &Id) -> Result<ClearBinStatus, Error>;
+
+    /// Get a bin summary by Id
+    fn get_bin_summary(&self, id: &Id) -> Result<Option<BinSummary>, Error>;
+
+    /// Get bin summaries for all currently-stored bins
+    fn get_bin_summaries(&self) -> Result<HashMap<Id, BinSummary>, Error>;
+
+    /// Get the config of a bin by Id
+    fn get_bin_config(&self, id: &Id) -> Result<Option<BinConfig>, Error>;
+
+    /// Replace the config of a bin by Id
+    fn set_bin_config(&mut self, id: &Id, config: BinConfig) -> Result<SetBinConfigStatus, Error>;
+
+    /// Deletes every bin that has expired as of `now` (in milliseconds since the Unix epoch),
+    /// returning their Ids.
+    fn delete_expired_bins(&mut self, now: i64) -> Result<Vec<Id>, Error> {
+        let expired: Vec<Id> = self.get_bin_summaries()?
+            .into_iter()
+            .filter(|(_, summary)| summary.expires_at.is_some_and(|at| at <= now))
+            .map(|(id, _)| id)
+            .collect();
+        for id in &expired {
+            self.delete_bin(id)?;
+        }
+        Ok(expired)
+    }
+
+    /// Insert a request into a Bin using a bin Id, giving it the next sequence number in that
+    /// bin. If the bin is at its `max_requests`, its oldest request is dropped.
+    fn insert_request(&mut self, id: &Id, request: Request) -> Result<InsertRequestStatus, Error>;
+}
+
+/// A simple in-memory implementation of Bins.
+#[derive(Debug)]
+pub struct InMemoryBins {
+    pub bins: HashMap<Id, InMemoryBin>,
+}
+
+/// A bin and its bookkeeping, as held by InMemoryBins.
+#[derive(Debug)]
+pub struct InMemoryBin {
+    pub options: BinOptions,
+    pub config: BinConfig,
+    pub created_at: i64,
+    pub total_request_count: usize,
+    pub requests: VecDeque<Request>,
+}
+
+impl InMemoryBin {
+    pub fn new(options: &BinOptions, created_at: i64) -> InMemoryBin {
+        InMemoryBin {
+            options: options.clone(),
+            config: BinConfig::default(),
+            created_at,
+            total_request_count: 0,
+            requests: VecDeque::new(),
+        }
+    }
+
+    /// Adds a request, evicting the oldest ones if that takes us past `max_requests`. Returns
+    /// the request as added.
+    pub fn push(&mut self, mut request: Request) -> Request {
+        self.total_request_count += 1;
+        request.seq = self.total_request_count;
+        self.requests.push_back(request.clone());
+        if let Some(max) = self.options.max_requests {
+            while self.requests.len() > max {
+                self.requests.pop_front();
+            }
+        }
+        request
+    }
+
+    /// Removes a request by Id, returning whether it was there.
+    pub fn remove(&mut self, request_id: &Id) -> bool {
+        match self.requests.iter().position(|r| &r.id == request_id) {
+            Some(idx) => self.requests.remove(idx).is_some(),
+            None => false,
+        }
+    }
+
+    pub fn summary(&self, id: &Id) -> BinSummary {
+        BinSummary {
+            id: id.to_owned(),
+            request_count: self.requests.len(),
+            total_request_count: self.total_request_count,
+            max_requests: self.options.max_requests,
+            created_at: self.created_at,
+            expires_at: self.options.expires_at(self.created_at),
+        }
+    }
+}
+
+impl InMemoryBins {
+    pub fn new() -> InMemoryBins {
+        InMemoryBins { bins: HashMap::new() }
+    }
+}
+
+impl Bins for InMemoryBins {
+    fn create_bin_with_options(&mut self, options: &BinOptions) -> Result<BinSummary, Error> {
+        let mut uuid = Id::random();
+        while self.bins.contains_key(&uuid) {
+            uuid = Id::random();
+        }
+        let bin = InMemoryBin::new(options, now_millis());
+        let summary = bin.summary(&uuid);
+        self.bins.insert(uuid, bin);
+        Ok(summary)
+    }
+
+    fn delete_bin(&mut self, id: &Id) -> Result<DeleteBinStatus, Error> {
+        match self.bins.remove(id) {
+            Some(_) => Ok(DeleteBinStatus::Ok),
+            _ => Ok(DeleteBinStatus::NoSuchBin),
+        }
+    }
+
+    fn get_bin_summary(&self, id: &Id) -> Result<Option<BinSummary>, Error> {
+        Ok(self.bins.get(id).map(|b| b.summary(id)))
+    }
+
+    fn get_bin_summaries(&self) -> Result<HashMap<Id, BinSummary>, Error> {
+        let mut map: HashMap<Id, BinSummary> = HashMap::new();
+        for (k, b) in self.bins.iter() {
+            map.insert(k.to_owned(), b.summary(k));
+        }
+        Ok(map)
+    }
+
+    fn get_bin(&self, id: &Id) -> Result<Option<Bin>, Error> {
+        Ok(self.bins.get(id).map(|b| b.requests.iter().cloned().collect()))
+    }
+
+    fn get_bin_range(&self, id: &Id, range: &RequestRange) -> Result<Option<Bin>, Error> {
+        Ok(self.bins.get(id).map(
+            |b| range.apply(b.requests.iter().cloned()),
+        ))
+    }
+
+    fn get_request(&self, id: &Id, request_id: &Id) -> Result<Option<Request>, Error> {
+        Ok(self.bins.get(id).and_then(|b| {
+            b.requests.iter().find(|r| &r.id == request_id).cloned()
+        }))
+    }
+
+    fn delete_request(&mut self, id: &Id, request_id: &Id) -> Result<DeleteRequestStatus, Error> {
+        if self.bins.get_mut(id).is_some_and(|bin| bin.remove(request_id)) {
+            Ok(DeleteRequestStatus::Ok)
+        } else {
+            Ok(DeleteRequestStatus::NoSuchRequest)
+        }
+    }
+
+    fn record_response(
+        &mut self,
+        id: &Id,
+        request_id: &Id,
+        response: RecordedResponse,
+    ) -> Result<RecordResponseStatus, Error> {
+        let request = self.bins.get_mut(id).and_then(|bin| {
+            bin.requests.iter_mut().find(|r| &r.id == request_id)
+        });
+        match request {
+            Some(request) => {
+                request.response = Some(response);
+                Ok(RecordResponseStatus::Ok)
+            }
+            None => Ok(RecordResponseStatus::NoSuchRequest),
+        }
+    }
+
+    fn clear_bin(&mut self, id: &Id) -> Result<ClearBinStatus, Error> {
+        match self.bins.get_mut(id) {
+            Some(bin) => {
+                bin.requests.clear();
+                Ok(ClearBinStatus::Ok)
+            }
+            None => Ok(ClearBinStatus::NoSuchBin),
+        }
+    }
+
+    fn get_bin_config(&self, id: &Id) -> Result<Option<BinConfig>, Error> {
+        Ok(self.bins.get(id).map(|b| b.config.clone()))
+    }
+
+    fn set_bin_config(&mut self, id: &Id, config: BinConfig) -> Result<SetBinConfigStatus, Error> {
+        match self.bins.get_mut(id) {
+            Some(bin) => {
+                bin.config = config;
+                Ok(SetBinConfigStatus::Ok)
+            }
+            None => Ok(SetBinConfigStatus::NoSuchBin),
+        }
+    }
+
+    fn insert_request(&mut self, id: &Id, request: Request) -> Result<InsertRequestStatus, Error> {
+        match self.bins.get_mut(id) {
+            Some(bin) => Ok(InsertRequestStatus::Ok(Box::new(bin.push(request)))),
+            None => Ok(InsertRequestStatus::NoSuchBin),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    bins_test_suite!(InMemoryBins::new());
+
+    #[test]
+    fn test_inmemory_bin_deletion() {
+        let mut bins = InMemoryBins::new();
+        let bin = bins.create_bin().unwrap();
+        bins.delete_bin(&bin.id).unwrap();
+        assert!(bins.bins.is_empty())
+    }
+}
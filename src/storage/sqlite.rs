@@ -0,0 +1,610 @@
+//! A SQLite-backed implementation of Bins.
+//!
+//! Bins and requests are stored in normalized tables so that captured traffic can be queried
+//! with plain SQL after the fact:
+//!
+//! - `bins`                 One row per bin
+//! - `requests`             One row per captured request, pointing at its bin
+//! - `request_headers`      One row per header value, pointing at its request
+//! - `request_query_params` One row per query parameter value, pointing at its request
+//! - `response_headers`     One row per header value of a recorded response, pointing at its request
+//!
+//! Each bin's BinConfig is kept as JSON in the `config` column of `bins`, and the response
+//! recorded for a request, if any, in the `response_status`, `response_body` and
+//! `response_latency_ms` columns of `requests`.
+//!
+//! Deleting a bin cascades to its requests and their headers, query parameters and response
+//! headers.
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::{Connection, OptionalExtension, Params, Transaction};
+
+use serde_json;
+
+use models::*;
+use errors::Error;
+use storage::{Bin, Bins, ClearBinStatus, DeleteBinStatus, DeleteRequestStatus,
+              InsertRequestStatus, Order, RecordResponseStatus, RequestRange, SetBinConfigStatus};
+
+/// Schema migrations, applied in order. The index of the last applied migration + 1 is kept
+/// in SQLite's `user_version`, so only ever append to this list.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE bins (
+        id TEXT PRIMARY KEY NOT NULL
+    );
+    CREATE TABLE requests (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        bin_id TEXT NOT NULL REFERENCES bins(id) ON DELETE CASCADE,
+        time INTEGER NOT NULL,
+        method TEXT NOT NULL,
+        path TEXT NOT NULL,
+        content_length INTEGER,
+        content_type TEXT,
+        body TEXT
+    );
+    CREATE INDEX requests_bin_id ON requests(bin_id, id);
+    CREATE TABLE request_headers (
+        request_id INTEGER NOT NULL REFERENCES requests(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        value TEXT NOT NULL
+    );
+    CREATE INDEX request_headers_request_id ON request_headers(request_id);
+    CREATE TABLE request_query_params (
+        request_id INTEGER NOT NULL REFERENCES requests(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        value TEXT NOT NULL
+    );
+    CREATE INDEX request_query_params_request_id ON request_query_params(request_id);
+    "#,
+    r#"
+    ALTER TABLE bins ADD COLUMN max_requests INTEGER;
+    ALTER TABLE bins ADD COLUMN total_request_count INTEGER NOT NULL DEFAULT 0;
+    UPDATE bins SET total_request_count = (
+        SELECT COUNT(*) FROM requests WHERE requests.bin_id = bins.id
+    );
+    "#,
+    r#"
+    ALTER TABLE bins ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE bins ADD COLUMN expires_at INTEGER;
+    CREATE INDEX bins_expires_at ON bins(expires_at);
+    "#,
+    r#"
+    UPDATE requests SET body = CAST(body AS BLOB) WHERE typeof(body) = 'text';
+    "#,
+    r#"
+    ALTER TABLE bins ADD COLUMN config TEXT;
+    "#,
+    r#"
+    ALTER TABLE requests ADD COLUMN uid TEXT;
+    ALTER TABLE requests ADD COLUMN seq INTEGER;
+    UPDATE requests SET
+        uid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4' ||
+            substr(lower(hex(randomblob(2))), 2) || '-' ||
+            substr('89ab', 1 + abs(random() % 4), 1) || substr(lower(hex(randomblob(2))), 2) ||
+            '-' || lower(hex(randomblob(6))),
+        seq = (SELECT total_request_count FROM bins WHERE bins.id = requests.bin_id) - (
+            SELECT COUNT(*) FROM requests later
+            WHERE later.bin_id = requests.bin_id AND later.id > requests.id
+        );
+    CREATE UNIQUE INDEX requests_uid ON requests(uid);
+    "#,
+    r#"
+    ALTER TABLE requests ADD COLUMN response_status INTEGER;
+    ALTER TABLE requests ADD COLUMN response_body BLOB;
+    CREATE TABLE response_headers (
+        request_id INTEGER NOT NULL REFERENCES requests(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        value TEXT NOT NULL
+    );
+    CREATE INDEX response_headers_request_id ON response_headers(request_id);
+    "#,
+    r#"
+    ALTER TABLE requests ADD COLUMN response_latency_ms INTEGER;
+    "#,
+];
+
+/// Selects (id, request_count, total_request_count, max_requests, created_at, expires_at) for
+/// bins, to be completed with an optional WHERE clause and a GROUP BY.
+const SUMMARY_QUERY: &str = "SELECT b.id, COUNT(r.id), b.total_request_count, b.max_requests,
+     b.created_at, b.expires_at
+     FROM bins b LEFT JOIN requests r ON r.bin_id = b.id";
+
+/// A Bins implementation that stores everything in a SQLite database.
+pub struct SqliteBins {
+    conn: Connection,
+    id_extractor: IdExtractor,
+}
+
+impl SqliteBins {
+    /// Opens (creating if necessary) a SQLite database at the given path, bringing its
+    /// schema up to date.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteBins, Error> {
+        SqliteBins::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens a SQLite database that only lives in memory. Mostly useful for testing.
+    pub fn open_in_memory() -> Result<SqliteBins, Error> {
+        SqliteBins::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<SqliteBins, Error> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn)?;
+        Ok(SqliteBins {
+            conn,
+            id_extractor: IdExtractor::new(),
+        })
+    }
+
+    fn bin_exists(&self, id: &Id) -> Result<bool, Error> {
+        let found = self.conn
+            .query_row("SELECT 1 FROM bins WHERE id = ?1", [id.value()], |_| Ok(()))
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    /// Runs SUMMARY_QUERY with the given tail and params, turning rows into BinSummaries.
+    fn load_summaries<P: Params>(&self, tail: &str, params: P) -> Result<Vec<BinSummary>, Error> {
+        let mut stmt = self.conn.prepare(&format!("{} {}", SUMMARY_QUERY, tail))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        })?;
+        let mut summaries = Vec::new();
+        for row in rows {
+            let (id, count, total, max, created_at, expires_at): (
+                String,
+                i64,
+                i64,
+                Option<i64>,
+                i64,
+                Option<i64>,
+            ) = row?;
+            match self.id_extractor.parse(&id) {
+                Some(id) => summaries.push(BinSummary {
+                    id,
+                    request_count: count as usize,
+                    total_request_count: total as usize,
+                    max_requests: max.map(|m| m as usize),
+                    created_at,
+                    expires_at,
+                }),
+                None => warn!("Skipping bin with malformed id in SQLite: {}", id),
+            }
+        }
+        Ok(summaries)
+    }
+
+    /// Loads the requests whose row ids are selected by the given SQL, along with their
+    /// headers, query parameters and recorded responses, in the given order.
+    fn load_requests<P: Params + Copy>(
+        &self,
+        selected: &str,
+        order: Order,
+        params: P,
+    ) -> Result<Bin, Error> {
+        let mut headers = self.load_pairs(
+            &format!(
+                "SELECT request_id, name, value FROM request_headers
+                 WHERE request_id IN ({}) ORDER BY request_id, position",
+                selected
+            ),
+            params,
+        )?;
+        let mut query_params = self.load_pairs(
+            &format!(
+                "SELECT request_id, name, value FROM request_query_params
+                 WHERE request_id IN ({}) ORDER BY request_id, position",
+                selected
+            ),
+            params,
+        )?;
+        let mut response_headers = self.load_pairs(
+            &format!(
+                "SELECT request_id, name, value FROM response_headers
+                 WHERE request_id IN ({}) ORDER BY request_id, position",
+                selected
+            ),
+            params,
+        )?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, time, method, path, content_length, content_type, body, uid, seq,
+             response_status, response_body, response_latency_ms
+             FROM requests WHERE id IN ({}) ORDER BY id {}",
+            selected,
+            match order {
+                Order::Asc => "ASC",
+                Order::Desc => "DESC",
+            }
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            let request_id: i64 = row.get(0)?;
+            let content_length: Option<i64> = row.get(4)?;
+            let uid: String = row.get(7)?;
+            let seq: i64 = row.get(8)?;
+            let response_status: Option<i64> = row.get(9)?;
+            let response_body: Option<Vec<u8>> = row.get(10)?;
+            let response_latency_ms: Option<i64> = row.get(11)?;
+            Ok((
+                request_id,
+                uid,
+                Request {
+                    id: Id::random(),
+                    seq: seq as usize,
+                    content_length: content_length.map(|l| l as u64),
+                    content_type: row.get(5)?,
+                    time: row.get(1)?,
+                    method: row.get(2)?,
+                    path: row.get(3)?,
+                    body: row.get::<_, Option<Vec<u8>>>(6)?.map(Body::new),
+                    headers: HashMap::new(),
+                    query_string: HashMap::new(),
+                    response: response_status.map(|status| {
+                        RecordedResponse {
+                            status: status as u16,
+                            headers: HashMap::new(),
+                            body: response_body.map(Body::new),
+                            latency_ms: response_latency_ms.unwrap_or(0) as u64,
+                        }
+                    }),
+                },
+            ))
+        })?;
+        let mut requests = Vec::new();
+        for row in rows {
+            let (request_id, uid, mut request) = row?;
+            match self.id_extractor.parse(&uid) {
+                Some(id) => request.id = id,
+                None => warn!("Request with malformed id in SQLite: {}", uid),
+            }
+            request.headers = headers.remove(&request_id).unwrap_or_default();
+            request.query_string = query_params.remove(&request_id).unwrap_or_default();
+            if let Some(ref mut response) = request.response {
+                response.headers = response_headers.remove(&request_id).unwrap_or_default();
+            }
+            requests.push(request);
+        }
+        Ok(requests)
+    }
+
+    /// Loads (request_id, name, value) rows into multi-maps keyed by request id.
+    fn load_pairs<P: Params>(
+        &self,
+        sql: &str,
+        params: P,
+    ) -> Result<HashMap<i64, HashMap<String, Vec<String>>>, Error> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        let mut by_request: HashMap<i64, HashMap<String, Vec<String>>> = HashMap::new();
+        for row in rows {
+            let (request_id, name, value): (i64, String, String) = row?;
+            by_request
+                .entry(request_id)
+                .or_default()
+                .entry(name)
+                .or_default()
+                .push(value);
+        }
+        Ok(by_request)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), Error> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        debug!("Applying SQLite migration {}", idx + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (idx + 1) as i64)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn insert_pairs(
+    tx: &Transaction,
+    table: &str,
+    request_id: i64,
+    pairs: &HashMap<String, Vec<String>>,
+) -> Result<(), Error> {
+    let mut stmt = tx.prepare(&format!(
+        "INSERT INTO {} (request_id, position, name, value) VALUES (?1, ?2, ?3, ?4)",
+        table
+    ))?;
+    let mut position: i64 = 0;
+    for (name, values) in pairs {
+        for value in values {
+            stmt.execute(params![request_id, position, name, value])?;
+            position += 1;
+        }
+    }
+    Ok(())
+}
+
+impl Bins for SqliteBins {
+    fn create_bin_with_options(&mut self, options: &BinOptions) -> Result<BinSummary, Error> {
+        let created_at = now_millis();
+        let expires_at = options.expires_at(created_at);
+        loop {
+            let id = Id::random();
+            let inserted = self.conn.execute(
+                "INSERT OR IGNORE INTO bins (id, max_requests, created_at, expires_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    id.value(),
+                    options.max_requests.map(|m| m as i64),
+                    created_at,
+                    expires_at,
+                ],
+            )?;
+            if inserted == 1 {
+                return Ok(BinSummary {
+                    id,
+                    request_count: 0,
+                    total_request_count: 0,
+                    max_requests: options.max_requests,
+                    created_at,
+                    expires_at,
+                });
+            }
+        }
+    }
+
+    fn delete_bin(&mut self, id: &Id) -> Result<DeleteBinStatus, Error> {
+        let deleted = self.conn
+            .execute("DELETE FROM bins WHERE id = ?1", [id.value()])?;
+        if deleted == 0 {
+            Ok(DeleteBinStatus::NoSuchBin)
+        } else {
+            Ok(DeleteBinStatus::Ok)
+        }
+    }
+
+    fn get_bin(&self, id: &Id) -> Result<Option<Bin>, Error> {
+        self.get_bin_range(id, &RequestRange::default())
+    }
+
+    fn get_bin_range(&self, id: &Id, range: &RequestRange) -> Result<Option<Bin>, Error> {
+        if !self.bin_exists(id)? {
+            return Ok(None);
+        }
+        let (direction, from_seq) = match range.order {
+            Order::Asc => ("ASC", "seq >= ?4"),
+            Order::Desc => ("DESC", "seq <= ?4"),
+        };
+        // A negative LIMIT means no limit to SQLite
+        let limit = range.limit.map_or(-1, |l| l.min(i64::MAX as usize) as i64);
+        let bin = self.load_requests(
+            &format!(
+                "SELECT id FROM requests WHERE bin_id = ?1 AND (?4 IS NULL OR {})
+                 ORDER BY id {} LIMIT ?2 OFFSET ?3",
+                from_seq,
+                direction
+            ),
+            range.order,
+            params![
+                id.value(),
+                limit,
+                range.offset.min(i64::MAX as usize) as i64,
+                range.from_seq.map(|seq| seq as i64),
+            ],
+        )?;
+        Ok(Some(bin))
+    }
+
+    fn get_request(&self, id: &Id, request_id: &Id) -> Result<Option<Request>, Error> {
+        let mut requests = self.load_requests(
+            "SELECT id FROM requests WHERE bin_id = ?1 AND uid = ?2",
+            Order::Asc,
+            params![id.value(), request_id.value()],
+        )?;
+        Ok(requests.pop())
+    }
+
+    fn delete_request(&mut self, id: &Id, request_id: &Id) -> Result<DeleteRequestStatus, Error> {
+        let deleted = self.conn.execute(
+            "DELETE FROM requests WHERE bin_id = ?1 AND uid = ?2",
+            params![id.value(), request_id.value()],
+        )?;
+        if deleted == 0 {
+            Ok(DeleteRequestStatus::NoSuchRequest)
+        } else {
+            Ok(DeleteRequestStatus::Ok)
+        }
+    }
+
+    fn record_response(
+        &mut self,
+        id: &Id,
+        request_id: &Id,
+        response: RecordedResponse,
+    ) -> Result<RecordResponseStatus, Error> {
+        let tx = self.conn.transaction()?;
+        let row_id: i64 = match tx.query_row(
+            "SELECT id FROM requests WHERE bin_id = ?1 AND uid = ?2",
+            params![id.value(), request_id.value()],
+            |row| row.get(0),
+        ).optional()? {
+            Some(row_id) => row_id,
+            None => return Ok(RecordResponseStatus::NoSuchRequest),
+        };
+        tx.execute(
+            "UPDATE requests SET response_status = ?2, response_body = ?3,
+             response_latency_ms = ?4 WHERE id = ?1",
+            params![
+                row_id,
+                response.status as i64,
+                response.body.as_ref().map(|b| b.bytes()),
+                response.latency_ms as i64,
+            ],
+        )?;
+        tx.execute("DELETE FROM response_headers WHERE request_id = ?1", [row_id])?;
+        insert_pairs(&tx, "response_headers", row_id, &response.headers)?;
+        tx.commit()?;
+        Ok(RecordResponseStatus::Ok)
+    }
+
+    fn clear_bin(&mut self, id: &Id) -> Result<ClearBinStatus, Error> {
+        if !self.bin_exists(id)? {
+            return Ok(ClearBinStatus::NoSuchBin);
+        }
+        self.conn
+            .execute("DELETE FROM requests WHERE bin_id = ?1", [id.value()])?;
+        Ok(ClearBinStatus::Ok)
+    }
+
+    fn get_bin_summary(&self, id: &Id) -> Result<Option<BinSummary>, Error> {
+        let mut summaries = self.load_summaries("WHERE b.id = ?1 GROUP BY b.id", [id.value()])?;
+        Ok(summaries.pop())
+    }
+
+    fn get_bin_summaries(&self) -> Result<HashMap<Id, BinSummary>, Error> {
+        let summaries = self.load_summaries("GROUP BY b.id", [])?;
+        Ok(summaries
+            .into_iter()
+            .map(|summary| (summary.id.to_owned(), summary))
+            .collect())
+    }
+
+    fn get_bin_config(&self, id: &Id) -> Result<Option<BinConfig>, Error> {
+        let config: Option<Option<String>> = self.conn
+            .query_row(
+                "SELECT config FROM bins WHERE id = ?1",
+                [id.value()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match config {
+            Some(Some(json)) => Ok(Some(serde_json::from_str(&json)?)),
+            Some(None) => Ok(Some(BinConfig::default())),
+            None => Ok(None),
+        }
+    }
+
+    fn set_bin_config(&mut self, id: &Id, config: BinConfig) -> Result<SetBinConfigStatus, Error> {
+        let updated = self.conn.execute(
+            "UPDATE bins SET config = ?2 WHERE id = ?1",
+            params![id.value(), serde_json::to_string(&config)?],
+        )?;
+        if updated == 0 {
+            Ok(SetBinConfigStatus::NoSuchBin)
+        } else {
+            Ok(SetBinConfigStatus::Ok)
+        }
+    }
+
+    fn insert_request(&mut self, id: &Id, request: Request) -> Result<InsertRequestStatus, Error> {
+        let (max_requests, total_request_count): (Option<i64>, i64) = match self.conn
+            .query_row(
+                "SELECT max_requests, total_request_count FROM bins WHERE id = ?1",
+                [id.value()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()? {
+            Some(found) => found,
+            None => return Ok(InsertRequestStatus::NoSuchBin),
+        };
+        let mut request = request;
+        request.seq = total_request_count as usize + 1;
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO requests (bin_id, time, method, path, content_length, content_type, body,
+             uid, seq)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                id.value(),
+                request.time,
+                request.method,
+                request.path,
+                request.content_length.map(|l| l as i64),
+                request.content_type,
+                request.body.as_ref().map(|b| b.bytes()),
+                request.id.value(),
+                request.seq as i64,
+            ],
+        )?;
+        let request_id = tx.last_insert_rowid();
+        insert_pairs(&tx, "request_headers", request_id, &request.headers)?;
+        insert_pairs(&tx, "request_query_params", request_id, &request.query_string)?;
+        tx.execute(
+            "UPDATE bins SET total_request_count = total_request_count + 1 WHERE id = ?1",
+            [id.value()],
+        )?;
+        if let Some(max) = max_requests {
+            tx.execute(
+                "DELETE FROM requests WHERE bin_id = ?1 AND id NOT IN (
+                     SELECT id FROM requests WHERE bin_id = ?1 ORDER BY id DESC LIMIT ?2
+                 )",
+                params![id.value(), max],
+            )?;
+        }
+        tx.commit()?;
+        Ok(InsertRequestStatus::Ok(Box::new(request)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    bins_test_suite!(SqliteBins::open_in_memory().unwrap());
+
+    #[test]
+    fn test_sqlite_bins_persist_across_reopen() {
+        let path = env::temp_dir().join(format!("rusqbin-test-{}.sqlite", Id::random()));
+        let persisted = sample_request("/persisted");
+        let bin = {
+            let mut bins = SqliteBins::open(&path).unwrap();
+            let bin = bins.create_bin().unwrap();
+            bins.insert_request(&bin.id, persisted.clone()).unwrap();
+            bin
+        };
+
+        let bins = SqliteBins::open(&path).unwrap();
+        assert_eq!(
+            bins.get_bin(&bin.id).unwrap().unwrap(),
+            vec![stored(&persisted, 1)]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sqlite_bins_normalized_tables() {
+        let mut bins = SqliteBins::open_in_memory().unwrap();
+        let bin = bins.create_bin().unwrap();
+        bins.insert_request(&bin.id, sample_request("/sql"))
+            .unwrap();
+
+        let multi: i64 = bins.conn
+            .query_row(
+                "SELECT COUNT(*) FROM request_headers WHERE name = 'X-Multi'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(multi, 2);
+
+        bins.delete_bin(&bin.id).unwrap();
+        let orphans: i64 = bins.conn
+            .query_row("SELECT COUNT(*) FROM request_query_params", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(orphans, 0);
+    }
+}
@@ -0,0 +1,518 @@
+//! A suite of tests that every Bins implementation in this module should pass.
+//!
+//! Invoke `bins_test_suite!(expr)` inside a test module, where `expr` evaluates to a fresh,
+//! empty implementation of Bins.
+use std::collections::HashMap;
+
+use models::*;
+
+/// Builds a Request with nothing but a method and a path, for tests to fill in whatever else
+/// they need.
+pub fn bare_request(method: &str, path: &str) -> Request {
+    Request {
+        id: Id::random(),
+        seq: 0,
+        content_length: None,
+        content_type: None,
+        time: 0,
+        method: method.to_owned(),
+        path: path.to_owned(),
+        body: None,
+        headers: HashMap::new(),
+        query_string: HashMap::new(),
+        response: None,
+    }
+}
+
+/// Builds a Request with a bit of everything in it.
+pub fn sample_request(path: &str) -> Request {
+    let mut headers = HashMap::new();
+    headers.insert("Host".to_owned(), vec!["localhost".to_owned()]);
+    headers.insert(
+        "X-Multi".to_owned(),
+        vec!["first".to_owned(), "second".to_owned()],
+    );
+    let mut query_string = HashMap::new();
+    query_string.insert("q".to_owned(), vec!["1".to_owned(), "2".to_owned()]);
+    Request {
+        content_length: Some(5),
+        content_type: Some("fake".to_owned()),
+        time: 123,
+        // Not valid UTF-8, to make sure bodies are kept as raw bytes
+        body: Some(Body::new(vec![b'b', b'o', b'd', b'y', 0xff])),
+        headers,
+        query_string,
+        ..bare_request("GET", path)
+    }
+}
+
+/// What a request should look like once stored with the given sequence number.
+pub fn stored(request: &Request, seq: usize) -> Request {
+    Request {
+        seq,
+        ..request.clone()
+    }
+}
+
+/// Builds a RecordedResponse with a bit of everything in it.
+pub fn sample_response() -> RecordedResponse {
+    let mut headers = HashMap::new();
+    headers.insert(
+        "Set-Cookie".to_owned(),
+        vec!["a=1".to_owned(), "b=2".to_owned()],
+    );
+    RecordedResponse {
+        status: 202,
+        headers,
+        body: Some(Body::new(vec![b'o', b'k', 0xff])),
+        latency_ms: 12,
+    }
+}
+
+/// Builds a BinConfig with a bit of everything in it.
+pub fn sample_config() -> BinConfig {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_owned(), "application/json".to_owned());
+    let mut json = HashMap::new();
+    json.insert("/kind".to_owned(), "conflict".into());
+    let mut states = HashMap::new();
+    states.insert(
+        "down".to_owned(),
+        ScenarioStep {
+            response: MockResponse {
+                status: 503,
+                headers: HashMap::new(),
+                body: None,
+                template: false,
+            },
+            next: Some("up".to_owned()),
+        },
+    );
+    states.insert(
+        "up".to_owned(),
+        ScenarioStep {
+            response: MockResponse {
+                status: 200,
+                headers: HashMap::new(),
+                body: None,
+                template: false,
+            },
+            next: None,
+        },
+    );
+    BinConfig {
+        response: Some(MockResponse {
+            status: 201,
+            headers,
+            body: Some(Body::from("{\"ack\": true}")),
+            template: false,
+        }),
+        rules: vec![
+            ResponseRule {
+                matcher: RequestMatcher {
+                    method: Some("POST".to_owned()),
+                    path: Some(Pattern::from("^/orders/")),
+                    json,
+                    ..RequestMatcher::default()
+                },
+                response: MockResponse {
+                    status: 409,
+                    headers: HashMap::new(),
+                    body: None,
+                    template: false,
+                },
+            },
+        ],
+        scenarios: vec![
+            Scenario {
+                name: "flaky".to_owned(),
+                matcher: RequestMatcher::default(),
+                initial_state: "down".to_owned(),
+                states,
+            },
+        ],
+        upstream: Some("http://localhost:9999/api".to_owned()),
+        faults: Faults {
+            delay_ms: 10,
+            error_percent: 5,
+            slow_body: Some(SlowBody {
+                chunk_size: 1,
+                chunk_delay_ms: 5,
+            }),
+            ..Faults::default()
+        },
+    }
+}
+
+macro_rules! bins_test_suite {
+    ($new_bins:expr) => {
+        use storage::test_suite::{sample_config, sample_request, sample_response, stored};
+
+        #[test]
+        fn test_bin_creation() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            assert_eq!(bin.request_count, 0);
+            assert!(bins.get_bin_summary(&bin.id).unwrap().is_some());
+        }
+
+        #[test]
+        fn test_bin_deletion() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            match bins.delete_bin(&bin.id).unwrap() {
+                DeleteBinStatus::Ok => (),
+                DeleteBinStatus::NoSuchBin => panic!("Could not delete a bin that was just created"),
+            }
+            assert!(bins.get_bin_summary(&bin.id).unwrap().is_none());
+            assert!(bins.get_bin(&bin.id).unwrap().is_none());
+            assert!(bins.get_bin_summaries().unwrap().is_empty());
+        }
+
+        #[test]
+        fn test_missing_bin_deletion() {
+            let mut bins = $new_bins;
+            match bins.delete_bin(&Id::random()).unwrap() {
+                DeleteBinStatus::NoSuchBin => (),
+                DeleteBinStatus::Ok => panic!("Deleted a bin that does not exist"),
+            }
+        }
+
+        #[test]
+        fn test_get_bin_summary() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            bins.insert_request(&bin.id, sample_request("/whoa")).unwrap();
+
+            let summary = bins.get_bin_summary(&bin.id).unwrap().unwrap();
+            assert_eq!(summary.request_count, 1);
+            assert_eq!(summary.total_request_count, 1);
+            assert_eq!(summary.max_requests, None)
+        }
+
+        #[test]
+        fn test_get_bin() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            let (whoa, there) = (sample_request("/whoa"), sample_request("/there"));
+            bins.insert_request(&bin.id, whoa.clone()).unwrap();
+            bins.insert_request(&bin.id, there.clone()).unwrap();
+
+            let requests = bins.get_bin(&bin.id).unwrap().unwrap();
+            assert_eq!(requests.len(), 2);
+            assert_eq!(requests[0], stored(&whoa, 1));
+            assert_eq!(requests[1], stored(&there, 2));
+        }
+
+        #[test]
+        fn test_get_bin_summaries() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            let other = bins.create_bin().unwrap();
+            bins.insert_request(&bin.id, sample_request("/whoa")).unwrap();
+
+            let summaries = bins.get_bin_summaries().unwrap();
+            assert_eq!(summaries.len(), 2);
+            assert_eq!(summaries.get(&bin.id).unwrap().request_count, 1);
+            assert_eq!(summaries.get(&other.id).unwrap().request_count, 0);
+        }
+
+        #[test]
+        fn test_max_requests_evicts_oldest() {
+            let mut bins = $new_bins;
+            let options = BinOptions {
+                max_requests: Some(2),
+                ..BinOptions::default()
+            };
+            let bin = bins.create_bin_with_options(&options).unwrap();
+            assert_eq!(bin.max_requests, Some(2));
+            let samples: Vec<Request> = ["/1", "/2", "/3"].iter().map(|p| sample_request(p)).collect();
+            for sample in &samples {
+                bins.insert_request(&bin.id, sample.clone()).unwrap();
+            }
+
+            let requests = bins.get_bin(&bin.id).unwrap().unwrap();
+            assert_eq!(requests, vec![stored(&samples[1], 2), stored(&samples[2], 3)]);
+            let summary = bins.get_bin_summary(&bin.id).unwrap().unwrap();
+            assert_eq!(summary.request_count, 2);
+            assert_eq!(summary.total_request_count, 3);
+            let summaries = bins.get_bin_summaries().unwrap();
+            assert_eq!(summaries.get(&bin.id), Some(&summary));
+        }
+
+        #[test]
+        fn test_get_bin_range() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            let first = sample_request("/1");
+            bins.insert_request(&bin.id, first.clone()).unwrap();
+            for path in &["/2", "/3", "/4"] {
+                bins.insert_request(&bin.id, sample_request(path)).unwrap();
+            }
+            let paths = |range: RequestRange| -> Vec<String> {
+                bins.get_bin_range(&bin.id, &range)
+                    .unwrap()
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.path)
+                    .collect()
+            };
+
+            assert_eq!(paths(RequestRange::default()), vec!["/1", "/2", "/3", "/4"]);
+            assert_eq!(
+                paths(RequestRange {
+                    from_seq: None,
+                    offset: 1,
+                    limit: Some(2),
+                    order: ::storage::Order::Asc,
+                }),
+                vec!["/2", "/3"]
+            );
+            assert_eq!(
+                paths(RequestRange {
+                    from_seq: None,
+                    offset: 1,
+                    limit: None,
+                    order: ::storage::Order::Desc,
+                }),
+                vec!["/3", "/2", "/1"]
+            );
+            assert_eq!(
+                paths(RequestRange {
+                    from_seq: Some(2),
+                    limit: Some(2),
+                    ..RequestRange::default()
+                }),
+                vec!["/2", "/3"]
+            );
+            assert_eq!(
+                paths(RequestRange {
+                    from_seq: Some(2),
+                    offset: 0,
+                    limit: None,
+                    order: ::storage::Order::Desc,
+                }),
+                vec!["/2", "/1"]
+            );
+            assert!(
+                paths(RequestRange {
+                    offset: 4,
+                    ..RequestRange::default()
+                }).is_empty()
+            );
+            assert_eq!(
+                paths(RequestRange {
+                    limit: Some(usize::MAX),
+                    ..RequestRange::default()
+                }).len(),
+                4
+            );
+            assert!(
+                paths(RequestRange {
+                    offset: usize::MAX,
+                    ..RequestRange::default()
+                }).is_empty()
+            );
+            assert_eq!(
+                bins.get_bin_range(&bin.id, &RequestRange::default())
+                    .unwrap()
+                    .unwrap()[0],
+                stored(&first, 1)
+            );
+            assert!(
+                bins.get_bin_range(&Id::random(), &RequestRange::default())
+                    .unwrap()
+                    .is_none()
+            );
+        }
+
+        #[test]
+        fn test_single_requests() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            let other = bins.create_bin().unwrap();
+            let (first, second) = (sample_request("/first"), sample_request("/second"));
+            match bins.insert_request(&bin.id, first.clone()).unwrap() {
+                InsertRequestStatus::Ok(inserted) => assert_eq!(*inserted, stored(&first, 1)),
+                InsertRequestStatus::NoSuchBin => panic!("Could not insert into a bin that was just created"),
+            }
+            bins.insert_request(&bin.id, second.clone()).unwrap();
+
+            assert_eq!(
+                bins.get_request(&bin.id, &second.id).unwrap(),
+                Some(stored(&second, 2))
+            );
+            assert!(bins.get_request(&other.id, &second.id).unwrap().is_none());
+            assert!(bins.get_request(&bin.id, &Id::random()).unwrap().is_none());
+
+            match bins.delete_request(&other.id, &first.id).unwrap() {
+                DeleteRequestStatus::NoSuchRequest => (),
+                DeleteRequestStatus::Ok => panic!("Deleted a request from the wrong bin"),
+            }
+            match bins.delete_request(&bin.id, &first.id).unwrap() {
+                DeleteRequestStatus::Ok => (),
+                DeleteRequestStatus::NoSuchRequest => panic!("Could not delete a request that was just inserted"),
+            }
+            assert!(bins.get_request(&bin.id, &first.id).unwrap().is_none());
+            assert_eq!(
+                bins.get_bin(&bin.id).unwrap().unwrap(),
+                vec![stored(&second, 2)]
+            );
+
+            // Sequence numbers keep counting up
+            let third = sample_request("/third");
+            bins.insert_request(&bin.id, third.clone()).unwrap();
+            assert_eq!(
+                bins.get_request(&bin.id, &third.id).unwrap(),
+                Some(stored(&third, 3))
+            );
+            let summary = bins.get_bin_summary(&bin.id).unwrap().unwrap();
+            assert_eq!(summary.request_count, 2);
+            assert_eq!(summary.total_request_count, 3);
+        }
+
+        #[test]
+        fn test_clear_bin() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            let other = bins.create_bin().unwrap();
+            bins.set_bin_config(&bin.id, sample_config()).unwrap();
+            bins.insert_request(&bin.id, sample_request("/first")).unwrap();
+            bins.insert_request(&bin.id, sample_request("/second")).unwrap();
+            let untouched = sample_request("/elsewhere");
+            bins.insert_request(&other.id, untouched.clone()).unwrap();
+
+            match bins.clear_bin(&bin.id).unwrap() {
+                ClearBinStatus::Ok => (),
+                ClearBinStatus::NoSuchBin => panic!("Could not clear a bin that was just created"),
+            }
+            assert!(bins.get_bin(&bin.id).unwrap().unwrap().is_empty());
+            assert_eq!(bins.get_bin_config(&bin.id).unwrap(), Some(sample_config()));
+            assert_eq!(
+                bins.get_bin(&other.id).unwrap().unwrap(),
+                vec![stored(&untouched, 1)]
+            );
+
+            // Sequence numbers carry on from before the bin was cleared
+            let third = sample_request("/third");
+            bins.insert_request(&bin.id, third.clone()).unwrap();
+            assert_eq!(bins.get_bin(&bin.id).unwrap().unwrap(), vec![stored(&third, 3)]);
+            let summary = bins.get_bin_summary(&bin.id).unwrap().unwrap();
+            assert_eq!(summary.request_count, 1);
+            assert_eq!(summary.total_request_count, 3);
+
+            match bins.clear_bin(&Id::random()).unwrap() {
+                ClearBinStatus::NoSuchBin => (),
+                ClearBinStatus::Ok => panic!("Cleared a bin that does not exist"),
+            }
+        }
+
+        #[test]
+        fn test_record_response() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            let (first, second) = (sample_request("/first"), sample_request("/second"));
+            bins.insert_request(&bin.id, first.clone()).unwrap();
+            bins.insert_request(&bin.id, second.clone()).unwrap();
+
+            match bins.record_response(&bin.id, &first.id, sample_response()).unwrap() {
+                RecordResponseStatus::Ok => (),
+                RecordResponseStatus::NoSuchRequest => {
+                    panic!("Could not record a response to a request that was just inserted")
+                }
+            }
+            let with_response = Request {
+                response: Some(sample_response()),
+                ..stored(&first, 1)
+            };
+            assert_eq!(
+                bins.get_request(&bin.id, &first.id).unwrap(),
+                Some(with_response.clone())
+            );
+            assert_eq!(
+                bins.get_bin(&bin.id).unwrap().unwrap(),
+                vec![with_response, stored(&second, 2)]
+            );
+
+            // Recording again replaces the earlier response
+            let replacement = RecordedResponse {
+                status: 500,
+                headers: HashMap::new(),
+                body: None,
+                latency_ms: 0,
+            };
+            bins.record_response(&bin.id, &first.id, replacement.clone()).unwrap();
+            assert_eq!(
+                bins.get_request(&bin.id, &first.id).unwrap().unwrap().response,
+                Some(replacement)
+            );
+
+            for &(bin_id, request_id) in &[(&bin.id, &Id::random()), (&Id::random(), &first.id)] {
+                match bins.record_response(bin_id, request_id, sample_response()).unwrap() {
+                    RecordResponseStatus::NoSuchRequest => (),
+                    RecordResponseStatus::Ok => {
+                        panic!("Recorded a response to a request that does not exist")
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_delete_expired_bins() {
+            let mut bins = $new_bins;
+            let options = BinOptions {
+                ttl_secs: Some(10),
+                ..BinOptions::default()
+            };
+            let expiring = bins.create_bin_with_options(&options).unwrap();
+            let forever = bins.create_bin().unwrap();
+            let expires_at = expiring.expires_at.unwrap();
+            assert_eq!(expires_at, expiring.created_at + 10_000);
+            assert_eq!(forever.expires_at, None);
+            assert_eq!(
+                bins.get_bin_summary(&expiring.id).unwrap().unwrap().expires_at,
+                Some(expires_at)
+            );
+
+            assert!(bins.delete_expired_bins(expires_at - 1).unwrap().is_empty());
+            assert_eq!(
+                bins.delete_expired_bins(expires_at).unwrap(),
+                vec![expiring.id.to_owned()]
+            );
+            assert!(bins.get_bin_summary(&expiring.id).unwrap().is_none());
+            assert!(bins.get_bin_summary(&forever.id).unwrap().is_some());
+        }
+
+        #[test]
+        fn test_bin_config() {
+            let mut bins = $new_bins;
+            let bin = bins.create_bin().unwrap();
+            assert_eq!(
+                bins.get_bin_config(&bin.id).unwrap(),
+                Some(BinConfig::default())
+            );
+
+            let config = sample_config();
+            match bins.set_bin_config(&bin.id, config.clone()).unwrap() {
+                SetBinConfigStatus::Ok => (),
+                SetBinConfigStatus::NoSuchBin => panic!("Could not configure a bin that was just created"),
+            }
+            assert_eq!(bins.get_bin_config(&bin.id).unwrap(), Some(config));
+
+            assert!(bins.get_bin_config(&Id::random()).unwrap().is_none());
+            match bins.set_bin_config(&Id::random(), BinConfig::default()).unwrap() {
+                SetBinConfigStatus::NoSuchBin => (),
+                SetBinConfigStatus::Ok => panic!("Configured a bin that does not exist"),
+            }
+        }
+
+        #[test]
+        fn test_insert_request_into_missing_bin() {
+            let mut bins = $new_bins;
+            match bins.insert_request(&Id::random(), sample_request("/nope")).unwrap() {
+                InsertRequestStatus::NoSuchBin => (),
+                InsertRequestStatus::Ok(_) => panic!("Inserted a request into a missing bin"),
+            }
+        }
+    };
+}
@@ -0,0 +1,244 @@
+//! Fills in mock responses from the requests they answer.
+//!
+//! A template is text with `{{expression}}`s in it, each of which gets replaced by a bit of
+//! the captured request:
+//!
+//! - `{{method}}`
+//! - `{{path}}`, without the query string, or `{{path.N}}` for its Nth segment, starting at 0
+//! - `{{query.NAME}}` and `{{headers.NAME}}` (whose name is compared case-insensitively), for
+//!   their first value
+//! - `{{json.POINTER}}`, for a field of a JSON body by JSON Pointer, e.g. `{{json./order/id}}`
+//! - `{{body}}`, for the whole body as text
+//! - `{{id}}` and `{{seq}}`, for the captured request's Id and sequence number
+//! - `{{uuid}}`, for a freshly-generated random UUID
+//! - `{{now}}`, for the current time in milliseconds since the Unix epoch
+//!
+//! Anything the request doesn't have is replaced by nothing.
+//!
+//! ```
+//! # use rusqbin::templates;
+//! assert!(templates::validate("{\"order\": \"{{path.1}}\"}").is_ok());
+//! assert!(templates::validate("{{nope}}").is_err());
+//! ```
+use serde_json::{self, Value};
+
+use models::{now_millis, Id, Request};
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// A bit of a captured request that a template can refer to.
+#[derive(PartialEq, Debug, Eq, Clone)]
+enum Expression {
+    Method,
+    Path,
+    PathSegment(usize),
+    Query(String),
+    Header(String),
+    Json(String),
+    Body,
+    Id,
+    Seq,
+    Uuid,
+    Now,
+}
+
+#[derive(PartialEq, Debug, Eq, Clone)]
+enum Part<'a> {
+    Text(&'a str),
+    Expression(Expression),
+}
+
+/// Checks that a template can be used, returning a description of the problem if not.
+pub fn validate(template: &str) -> Result<(), String> {
+    parse(template).map(|_| ())
+}
+
+/// Fills in a template from the given request. Invalid templates are left as they are.
+pub fn render(template: &str, request: &Request) -> String {
+    let parts = match parse(template) {
+        Ok(parts) => parts,
+        Err(_) => return template.to_owned(),
+    };
+    let mut rendered = String::with_capacity(template.len());
+    for part in parts {
+        match part {
+            Part::Text(text) => rendered.push_str(text),
+            Part::Expression(expression) => rendered.push_str(&evaluate(&expression, request)),
+        }
+    }
+    rendered
+}
+
+/// Fills in a template for a header value from the given request, like `render`, dropping any
+/// control characters (apart from tabs) that were filled in, so that e.g. a `{{body}}` with line
+/// breaks in it can't add headers of its own.
+pub fn render_header(template: &str, request: &Request) -> String {
+    render(template, request)
+        .chars()
+        .filter(|c| *c == '\t' || !c.is_control())
+        .collect()
+}
+
+fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(OPEN) {
+        if start > 0 {
+            parts.push(Part::Text(&rest[..start]));
+        }
+        let after_open = &rest[start + OPEN.len()..];
+        let end = after_open.find(CLOSE).ok_or_else(|| {
+            format!("Unclosed template expression: {}", &rest[start..])
+        })?;
+        parts.push(Part::Expression(parse_expression(after_open[..end].trim())?));
+        rest = &after_open[end + CLOSE.len()..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+fn parse_expression(s: &str) -> Result<Expression, String> {
+    let mut parts = s.splitn(2, '.');
+    match (parts.next().unwrap_or(""), parts.next()) {
+        ("method", None) => Ok(Expression::Method),
+        ("path", None) => Ok(Expression::Path),
+        ("path", Some(idx)) => {
+            idx.parse().map(Expression::PathSegment).map_err(|_| {
+                format!("Invalid path segment in template: {}", s)
+            })
+        }
+        ("query", Some(name)) if !name.is_empty() => Ok(Expression::Query(name.to_owned())),
+        ("headers", Some(name)) if !name.is_empty() => Ok(Expression::Header(name.to_owned())),
+        ("json", Some(pointer)) if pointer.is_empty() || pointer.starts_with('/') => {
+            Ok(Expression::Json(pointer.to_owned()))
+        }
+        ("body", None) => Ok(Expression::Body),
+        ("id", None) => Ok(Expression::Id),
+        ("seq", None) => Ok(Expression::Seq),
+        ("uuid", None) => Ok(Expression::Uuid),
+        ("now", None) => Ok(Expression::Now),
+        _ => Err(format!("Unknown template expression: {}", s)),
+    }
+}
+
+fn evaluate(expression: &Expression, request: &Request) -> String {
+    let path = request.path.split('?').next().unwrap_or("");
+    match *expression {
+        Expression::Method => request.method.to_owned(),
+        Expression::Path => path.to_owned(),
+        Expression::PathSegment(idx) => {
+            path.trim_start_matches('/')
+                .split('/')
+                .nth(idx)
+                .unwrap_or("")
+                .to_owned()
+        }
+        Expression::Query(ref name) => {
+            request
+                .query_string
+                .get(name)
+                .and_then(|values| values.first())
+                .cloned()
+                .unwrap_or_default()
+        }
+        Expression::Header(ref name) => {
+            request
+                .headers
+                .iter()
+                .find(|&(k, _)| k.eq_ignore_ascii_case(name))
+                .and_then(|(_, values)| values.first())
+                .cloned()
+                .unwrap_or_default()
+        }
+        Expression::Json(ref pointer) => {
+            let body: Option<Value> = request.body.as_ref().and_then(|b| {
+                serde_json::from_slice(b.bytes()).ok()
+            });
+            match body.as_ref().and_then(|b| b.pointer(pointer)) {
+                Some(Value::String(s)) => s.to_owned(),
+                Some(value) => value.to_string(),
+                None => String::new(),
+            }
+        }
+        Expression::Body => {
+            request
+                .body
+                .as_ref()
+                .and_then(|b| b.text())
+                .unwrap_or("")
+                .to_owned()
+        }
+        Expression::Id => request.id.value().to_owned(),
+        Expression::Seq => request.seq.to_string(),
+        Expression::Uuid => Id::random().value().to_owned(),
+        Expression::Now => now_millis().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::{Body, IdExtractor};
+    use std::collections::HashMap;
+    use storage::test_suite::bare_request;
+
+    fn request() -> Request {
+        let mut headers = HashMap::new();
+        headers.insert("x-correlation-id".to_owned(), vec!["abc-123".to_owned()]);
+        let mut query_string = HashMap::new();
+        query_string.insert("page".to_owned(), vec!["2".to_owned(), "3".to_owned()]);
+        Request {
+            seq: 7,
+            body: Some(Body::from(r#"{"order": {"id": 42, "ref": "r-1"}}"#)),
+            headers,
+            query_string,
+            ..bare_request("POST", "/orders/42/items?page=2&page=3")
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let req = request();
+        let render = |template: &str| render(template, &req);
+        assert_eq!(render("no expressions"), "no expressions");
+        assert_eq!(render("{{method}} {{ path }}"), "POST /orders/42/items");
+        assert_eq!(render("{{path.0}}/{{path.1}}/{{path.9}}"), "orders/42/");
+        assert_eq!(render("{{query.page}}{{query.size}}"), "2");
+        assert_eq!(render("{{headers.X-Correlation-Id}}"), "abc-123");
+        assert_eq!(
+            render(r#"{"id": {{json./order/id}}, "ref": "{{json./order/ref}}"}"#),
+            r#"{"id": 42, "ref": "r-1"}"#
+        );
+        assert_eq!(render("{{json./missing}}"), "");
+        assert_eq!(render("{{body}}"), r#"{"order": {"id": 42, "ref": "r-1"}}"#);
+        assert_eq!(render("{{id}}"), req.id.value());
+        assert_eq!(render("{{seq}}"), "7");
+        assert!(IdExtractor::new().parse(&render("{{uuid}}")).is_some());
+        assert!(render("{{now}}").parse::<i64>().unwrap() > 0);
+        assert_eq!(render("{{unclosed"), "{{unclosed");
+    }
+
+    #[test]
+    fn test_render_header() {
+        let req = Request {
+            body: Some(Body::from("a\r\nSet-Cookie: b\u{7f}")),
+            ..request()
+        };
+        assert_eq!(render_header("{{body}}", &req), "aSet-Cookie: b");
+        assert_eq!(render_header("x\t{{method}}", &req), "x\tPOST");
+        assert_eq!(render_header("{{headers.X-Correlation-Id}}", &req), "abc-123");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("{{path.1}} {{json.}} {{json./a}} {{ headers.X-Id }}").is_ok());
+        assert!(validate("{{path.first}}").is_err());
+        assert!(validate("{{json.a}}").is_err());
+        assert!(validate("{{query.}}").is_err());
+        assert!(validate("{{unknown}}").is_err());
+        assert!(validate("{{method").is_err());
+    }
+}
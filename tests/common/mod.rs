@@ -8,9 +8,9 @@ extern crate tokio_core;
 extern crate futures;
 
 use self::rusqbin::server::XRusqBinId;
-use self::rusqbin::server::BinsServer;
-use self::rusqbin::models::{BinSummary, Request, Id};
-use self::rusqbin::storage::InMemoryBins;
+use self::rusqbin::server::{BinsServer, ServerConfig};
+use self::rusqbin::models::{BinConfig, BinOptions, BinSummary, Request, Id};
+use self::rusqbin::storage::{Bins, InMemoryBins};
 
 use hyper::{Body, Method, StatusCode, Uri};
 use hyper::client::{Response, FutureResponse};
@@ -31,14 +31,20 @@ use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 
 static PORT_NUM: AtomicUsize = ATOMIC_USIZE_INIT;
 
-pub struct TestEnv {
-    pub server: Arc<BinsServer<InMemoryBins>>,
+pub struct TestEnv<T = InMemoryBins>
+where
+    T: Bins + Send + 'static,
+{
+    pub server: Arc<BinsServer<T>>,
     core: tokio_core::reactor::Core,
 }
 
 #[allow(dead_code)]
-impl TestEnv {
-    pub fn new(server: Arc<BinsServer<InMemoryBins>>) -> TestEnv {
+impl<T> TestEnv<T>
+where
+    T: Bins + Send + 'static,
+{
+    pub fn new(server: Arc<BinsServer<T>>) -> TestEnv<T> {
         let core = tokio_core::reactor::Core::new().unwrap();
         TestEnv { server, core }
     }
@@ -52,9 +58,29 @@ impl TestEnv {
         F: FnOnce(&Client<HttpConnector, Body>) -> Fut,
         E: std::error::Error,
         Fut: Future<Item = I, Error = E>,
+    {
+        self.try_with_client(f).unwrap()
+    }
+
+    pub fn try_with_client<F, Fut, I, E>(&mut self, f: F) -> Result<I, E>
+    where
+        F: FnOnce(&Client<HttpConnector, Body>) -> Fut,
+        Fut: Future<Item = I, Error = E>,
     {
         let fut = f(&self.client());
-        self.core.run(fut).unwrap()
+        self.core.run(fut)
+    }
+
+    /// Runs a future that doesn't need a client to completion.
+    pub fn run<F>(&mut self, f: F) -> Result<F::Item, F::Error>
+    where
+        F: Future,
+    {
+        self.core.run(f)
+    }
+
+    pub fn handle(&self) -> tokio_core::reactor::Handle {
+        self.core.handle()
     }
 
     pub fn client(&self) -> Client<HttpConnector, Body> {
@@ -79,6 +105,19 @@ impl TestEnv {
         Ok(serde_json::from_str(&*string)?)
     }
 
+    pub fn create_bin_with_options(
+        &mut self,
+        options: &BinOptions,
+    ) -> Result<BinSummary, Box<dyn Error>> {
+        let path = format!("{}/rusqbins", self.base_uri());
+        let uri = Uri::from_str(path.as_str())?;
+        let mut req = HyperRequest::new(Method::Post, uri);
+        req.set_body(serde_json::to_string(options)?);
+        let resp = self.with_client(|client| client.request(req));
+        let string = self.get_body(resp);
+        Ok(serde_json::from_str(&string)?)
+    }
+
     pub fn get_bin_summary(&mut self, bin_id: &Id) -> Result<BinSummary, Box<Error>> {
         let uri = Uri::from_str(&*format!("{}/rusqbins/{}", self.base_uri(), bin_id))?;
         let req = HyperRequest::new(Method::Get, uri);
@@ -96,6 +135,35 @@ impl TestEnv {
         Ok(resp.status() == StatusCode::Ok)
     }
 
+    pub fn clear_bin_requests(&mut self, bin_id: &Id) -> Result<bool, Box<dyn Error>> {
+        let req = HyperRequest::new(
+            Method::Delete,
+            Uri::from_str(&format!("{}/rusqbins/{}/requests", self.base_uri(), bin_id))?,
+        );
+        let resp: Response = self.with_client(|c| c.request(req));
+        Ok(resp.status() == StatusCode::Ok)
+    }
+
+    pub fn set_bin_config(
+        &mut self,
+        bin_id: &Id,
+        config: &BinConfig,
+    ) -> Result<bool, Box<dyn Error>> {
+        let uri = Uri::from_str(&format!("{}/rusqbins/{}/config", self.base_uri(), bin_id))?;
+        let mut req = HyperRequest::new(Method::Put, uri);
+        req.set_body(serde_json::to_string(config)?);
+        let resp: Response = self.with_client(|c| c.request(req));
+        Ok(resp.status() == StatusCode::Ok)
+    }
+
+    pub fn get_bin_config(&mut self, bin_id: &Id) -> Result<BinConfig, Box<dyn Error>> {
+        let uri = Uri::from_str(&format!("{}/rusqbins/{}/config", self.base_uri(), bin_id))?;
+        let req = HyperRequest::new(Method::Get, uri);
+        let resp = self.with_client(|client| client.request(req));
+        let config_string = self.get_body(resp);
+        Ok(serde_json::from_str(&config_string)?)
+    }
+
     pub fn get_bin_requests(&mut self, bin_id: &Id) -> Result<Vec<Request>, Box<Error>> {
         let req = HyperRequest::new(
             Method::Get,
@@ -110,6 +178,23 @@ impl TestEnv {
         Ok(serde_json::from_str(&*summary_string)?)
     }
 
+    /// Gets the paths of a bin's requests that match the given filter query string.
+    pub fn get_filtered_bin_paths(
+        &mut self,
+        bin_id: &Id,
+        query: &str,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let uri = Uri::from_str(&format!(
+            "{}/rusqbins/{}/requests?{}",
+            self.base_uri(),
+            bin_id,
+            query
+        ))?;
+        let resp: Response = self.with_client(|c| c.request(HyperRequest::new(Method::Get, uri)));
+        let requests: Vec<Request> = serde_json::from_str(&self.get_body(resp))?;
+        Ok(requests.into_iter().map(|r| r.path).collect())
+    }
+
     // Fires sets of 3 requests in parallel
     pub fn parallel_requests(
         &mut self,
@@ -158,19 +243,43 @@ pub struct ServerRequest<'a> {
     pub headers: Headers,
 }
 
+/// Hands out a port that no other test is using.
+pub fn next_port() -> usize {
+    let mut p: usize = PORT_NUM.fetch_add(1, Ordering::SeqCst);
+    while p < 5000 {
+        p = PORT_NUM.fetch_add(1, Ordering::SeqCst);
+    }
+    p
+}
+
 /// Integration tests: server is started and stopped and requests are made to
 /// and from it to get end-to-end testing.
 ///
-pub fn run_with_server<T>(test: T) -> ()
+pub fn run_with_server<F>(test: F)
 where
-    T: FnOnce(TestEnv) -> (),
+    F: FnOnce(TestEnv),
 {
-    let mut p: usize = PORT_NUM.fetch_add(1, Ordering::SeqCst);
-    while p < 5000 {
-        p = PORT_NUM.fetch_add(1, Ordering::SeqCst);
-    }
+    run_with_server_using(InMemoryBins::new(), test)
+}
+
+/// Like run_with_server, but lets you choose the storage used by the server.
+pub fn run_with_server_using<T, F>(bins: T, test: F)
+where
+    T: Bins + Send + 'static,
+    F: FnOnce(TestEnv<T>),
+{
+    run_with_server_config(bins, ServerConfig::default(), test)
+}
+
+/// Like run_with_server, but lets you choose both the storage and the settings of the server.
+pub fn run_with_server_config<T, F>(bins: T, config: ServerConfig, test: F)
+where
+    T: Bins + Send + 'static,
+    F: FnOnce(TestEnv<T>),
+{
+    let p = next_port();
     // set up
-    let s = Arc::new(BinsServer::new(p, InMemoryBins::new()));
+    let s = Arc::new(BinsServer::with_config(p, bins, config));
     let still_running = Arc::new(Mutex::new(true));
     let s_spawn = s.clone();
     let running_spawn = still_running.clone();
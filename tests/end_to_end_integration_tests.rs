@@ -1,18 +1,35 @@
 #[macro_use]
 extern crate hyper;
 extern crate rusqbin;
+#[macro_use]
 extern crate serde_json;
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_tungstenite;
+extern crate url;
 
-use self::rusqbin::models::BinSummary;
+use self::rusqbin::models::{BinConfig, BinOptions, BinSummary, Body, MockResponse,
+                            RecordedResponse, ReplayOutcome};
 
 use hyper::client::Request as HyperRequest;
 use hyper::StatusCode;
-use hyper::header::{Headers, Header};
+use hyper::header::{Headers, Header, Host};
 use hyper::Method;
 
+use futures::{future, Future, Sink, Stream};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Timeout;
+use tokio_tungstenite::{client_async, WebSocketStream};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use url::Url;
+
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 use rusqbin::models::{Request, Id};
+use rusqbin::storage::*;
+use rusqbin::errors::Error;
+use rusqbin::server::{ServerConfig, XRusqBinId};
 
 mod common;
 use common::*;
@@ -155,8 +172,1221 @@ fn test_requesting_bin_requests() {
                 .is_some()
         );
 
-        assert_eq!(req.body, Some("hey there.".to_owned()));
+        assert_eq!(req.body, Some(Body::from("hey there.")));
         assert_eq!(req.method, Method::Post.as_ref());
 
     })
 }
+
+#[test]
+fn test_bin_max_requests_evicts_oldest() {
+    run_with_server(|mut test_env| {
+        let options = BinOptions {
+            max_requests: Some(1),
+            ..BinOptions::default()
+        };
+        let bin = test_env.create_bin_with_options(&options).unwrap();
+        assert_eq!(bin.max_requests, Some(1));
+
+        for path in &["/first", "/second"] {
+            let requests = vec![
+                ServerRequest {
+                    method: Method::Get,
+                    headers: Headers::new(),
+                    path,
+                    body: None,
+                },
+            ];
+            test_env.parallel_requests(&bin.id, &requests, 1);
+        }
+
+        let summary = test_env.get_bin_summary(&bin.id).unwrap();
+        assert_eq!(summary.request_count, 1);
+        assert_eq!(summary.total_request_count, 2);
+        let requests = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(requests[0].path, "/second");
+    })
+}
+
+#[test]
+fn test_bin_ttl() {
+    run_with_server(|mut test_env| {
+        let options = BinOptions {
+            ttl_secs: Some(60),
+            ..BinOptions::default()
+        };
+        let bin = test_env.create_bin_with_options(&options).unwrap();
+        assert_eq!(bin.expires_at, Some(bin.created_at + 60_000));
+        let summary = test_env.get_bin_summary(&bin.id).unwrap();
+        assert_eq!(summary.expires_at, bin.expires_at);
+    })
+}
+
+#[test]
+fn test_creating_bin_with_invalid_options() {
+    run_with_server(|mut test_env| {
+        let mut req = HyperRequest::new(
+            Method::Post,
+            test_env.to_uri(format!("{}/rusqbins", test_env.base_uri())),
+        );
+        req.set_body("{ \"max_requests\": \"lots\" }");
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::BadRequest);
+
+        for options in &[
+            r#"{ "max_requests": 0 }"#,
+            r#"{ "ttl_secs": 0 }"#,
+            r#"{ "ttl_secs": 100000000000000000 }"#,
+        ]
+        {
+            let mut req = HyperRequest::new(
+                Method::Post,
+                test_env.to_uri(format!("{}/rusqbins", test_env.base_uri())),
+            );
+            req.set_body(*options);
+            let resp = test_env.with_client(|c| c.request(req));
+            assert_eq!(resp.status(), StatusCode::BadRequest);
+        }
+        // The server is still up and running
+        assert!(test_env.create_bin().is_ok());
+    })
+}
+
+#[test]
+fn test_requests_with_binary_bodies() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let payload = vec![0x1f, 0x8b, 0x08, 0x00, 0xff];
+        let mut req = HyperRequest::new(
+            Method::Post,
+            test_env.to_uri(format!("{}/upload", test_env.base_uri())),
+        );
+        req.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+        req.set_body(payload.clone());
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::Ok);
+
+        let requests = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(requests.len(), 1);
+        let body = requests[0].body.as_ref().unwrap();
+        assert_eq!(body.bytes(), &payload[..]);
+        assert_eq!(body.text(), None);
+    })
+}
+
+#[test]
+fn test_bin_mock_response() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let mut headers = HashMap::new();
+        headers.insert("X-Ack".to_owned(), "yes".to_owned());
+        let config = BinConfig {
+            response: Some(MockResponse {
+                status: 201,
+                headers,
+                body: Some(Body::from("{\"ack\":true}")),
+                template: false,
+            }),
+            ..BinConfig::default()
+        };
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+        assert_eq!(test_env.get_bin_config(&bin.id).unwrap(), config);
+
+        let mut req = HyperRequest::new(
+            Method::Post,
+            test_env.to_uri(format!("{}/webhook", test_env.base_uri())),
+        );
+        req.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::Created);
+        assert_eq!(resp.headers().get_raw("X-Ack").unwrap(), "yes");
+        assert_eq!(test_env.get_body(resp), "{\"ack\":true}");
+        assert_eq!(test_env.get_bin_requests(&bin.id).unwrap().len(), 1);
+    })
+}
+
+#[test]
+fn test_bin_response_rules() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let config: BinConfig = serde_json::from_str(
+            r#"{
+              "response": { "status": 202 },
+              "rules": [
+                {
+                  "match": { "method": "POST", "path": "^/orders$", "json": { "/id": 1 } },
+                  "response": { "status": 409, "body": "duplicate" }
+                },
+                {
+                  "match": { "headers": { "X-Doodle": "yes" } },
+                  "response": { "status": 201 }
+                }
+              ]
+            }"#,
+        ).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+
+        let mut send = |path: &str, body: &'static str, doodle: bool| {
+            let mut req = HyperRequest::new(
+                Method::Post,
+                test_env.to_uri(format!("{}{}", test_env.base_uri(), path)),
+            );
+            req.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+            if doodle {
+                req.headers_mut().set(XDoodle("yes".to_owned()));
+            }
+            req.set_body(body);
+            test_env.with_client(|c| c.request(req)).status()
+        };
+        assert_eq!(send("/orders", "{\"id\": 1}", false), StatusCode::Conflict);
+        assert_eq!(send("/orders", "{\"id\": 2}", true), StatusCode::Created);
+        assert_eq!(send("/orders", "{\"id\": 2}", false), StatusCode::Accepted);
+        assert_eq!(send("/other", "{\"id\": 1}", false), StatusCode::Accepted);
+    })
+}
+
+#[test]
+fn test_bin_response_templates() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let config: BinConfig = serde_json::from_str(
+            r#"{
+              "response": {
+                "status": 201,
+                "headers": { "X-Correlation-Id": "{{headers.X-Correlation-Id}}" },
+                "body": "{\"order\": \"{{path.1}}\", \"ref\": \"{{json./ref}}\", \"seq\": {{seq}}}",
+                "template": true
+              }
+            }"#,
+        ).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+
+        let mut req = HyperRequest::new(
+            Method::Post,
+            test_env.to_uri(format!("{}/orders/42", test_env.base_uri())),
+        );
+        req.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+        req.headers_mut().set_raw("X-Correlation-Id", "abc-123");
+        req.set_body(r#"{"ref": "r-1"}"#);
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::Created);
+        assert_eq!(
+            resp.headers().get_raw("X-Correlation-Id").and_then(|v| v.one()),
+            Some(&b"abc-123"[..])
+        );
+        assert_eq!(
+            test_env.get_body(resp),
+            r#"{"order": "42", "ref": "r-1", "seq": 1}"#
+        );
+
+        let invalid: BinConfig = serde_json::from_str(
+            r#"{ "response": { "status": 200, "body": "{{nope}}", "template": true } }"#,
+        ).unwrap();
+        assert!(!test_env.set_bin_config(&bin.id, &invalid).unwrap());
+    })
+}
+
+#[test]
+fn test_bin_upstream() {
+    run_with_server(|mut test_env| {
+        // Another bin on the same server stands in for the upstream
+        let upstream = test_env.create_bin().unwrap();
+        let upstream_config: BinConfig = serde_json::from_str(
+            r#"{
+              "response": {
+                "status": 201,
+                "headers": { "X-Upstream": "yes" },
+                "body": "{{method}} {{path}}",
+                "template": true
+              }
+            }"#,
+        ).unwrap();
+        assert!(test_env.set_bin_config(&upstream.id, &upstream_config).unwrap());
+
+        let bin = test_env.create_bin().unwrap();
+        let config: BinConfig = serde_json::from_value(json!({
+            "upstream": format!("{}/{}/", test_env.base_uri(), upstream.id),
+            "rules": [{ "match": { "path": "^/mocked" }, "response": { "status": 418 } }]
+        })).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+
+        let mut req = HyperRequest::new(
+            Method::Post,
+            test_env.to_uri(format!("{}/orders/42?page=2", test_env.base_uri())),
+        );
+        req.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+        req.headers_mut().set_raw("X-Correlation-Id", "abc-123");
+        req.set_body("hello");
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::Created);
+        assert_eq!(
+            resp.headers().get_raw("X-Upstream").and_then(|v| v.one()),
+            Some(&b"yes"[..])
+        );
+        assert_eq!(test_env.get_body(resp), "POST /orders/42");
+
+        // Rules still take precedence over the upstream
+        let mut mocked = HyperRequest::new(
+            Method::Get,
+            test_env.to_uri(format!("{}/mocked", test_env.base_uri())),
+        );
+        mocked.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+        let resp = test_env.with_client(|c| c.request(mocked));
+        assert_eq!(resp.status(), StatusCode::ImATeapot);
+
+        let forwarded = test_env.get_bin_requests(&upstream.id).unwrap();
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0].method, "POST");
+        assert_eq!(forwarded[0].path, "/orders/42?page=2");
+        assert_eq!(forwarded[0].body, Some(Body::from("hello")));
+        assert_eq!(
+            forwarded[0].headers.get("X-Correlation-Id"),
+            Some(&vec!["abc-123".to_owned()])
+        );
+
+        let captured = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(captured.len(), 2);
+        let recorded: &RecordedResponse = captured[0].response.as_ref().unwrap();
+        assert_eq!(recorded.status, 201);
+        assert_eq!(recorded.headers.get("X-Upstream"), Some(&vec!["yes".to_owned()]));
+        assert_eq!(recorded.body, Some(Body::from("POST /orders/42")));
+        assert_eq!(captured[1].response.as_ref().map(|r| r.status), Some(418));
+
+        let https: BinConfig =
+            serde_json::from_value(json!({ "upstream": "https://example.com" })).unwrap();
+        assert!(!test_env.set_bin_config(&bin.id, &https).unwrap());
+    })
+}
+
+#[test]
+fn test_sent_responses_are_recorded() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let send = |test_env: &mut TestEnv, path: &str| {
+            let mut req = HyperRequest::new(
+                Method::Get,
+                test_env.to_uri(format!("{}{}", test_env.base_uri(), path)),
+            );
+            req.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+            let resp = test_env.with_client(|c| c.request(req));
+            test_env.get_body(resp)
+        };
+
+        send(&mut test_env, "/plain");
+        let config: BinConfig = serde_json::from_value(json!({
+            "response": {
+                "status": 201,
+                "headers": { "X-Mock": "yes" },
+                "body": "hi {{path}}",
+                "template": true
+            },
+            "faults": { "delay_ms": 100, "slow_body": { "chunk_size": 1, "chunk_delay_ms": 1 } }
+        })).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+        assert_eq!(send(&mut test_env, "/mocked"), "hi /mocked");
+        let failing: BinConfig = serde_json::from_value(json!({
+            "response": { "status": 201, "body": "never sent" },
+            "faults": { "error_percent": 100, "error_status": 503 }
+        })).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &failing).unwrap());
+        assert_eq!(send(&mut test_env, "/failing"), "");
+
+        let responses: Vec<RecordedResponse> = test_env
+            .get_bin_requests(&bin.id)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.response.unwrap())
+            .collect();
+        assert_eq!(responses.len(), 3);
+        assert_eq!((responses[0].status, &responses[0].body), (200, &None));
+
+        assert_eq!(responses[1].status, 201);
+        assert_eq!(responses[1].headers.get("X-Mock"), Some(&vec!["yes".to_owned()]));
+        assert_eq!(responses[1].body, Some(Body::from("hi /mocked")));
+        assert!(responses[1].latency_ms >= 100);
+
+        // Injected errors are what the client got back, so they are what gets recorded
+        assert_eq!((responses[2].status, &responses[2].body), (503, &None));
+    })
+}
+
+#[test]
+fn test_replay_requests() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let target = test_env.create_bin().unwrap();
+        for &(ref method, path, body) in &[
+            (Method::Post, "/hooks/paid?attempt=1", "paid"),
+            (Method::Put, "/hooks/refunded", "refunded"),
+            (Method::Post, "/hooks/disputed", "disputed"),
+        ]
+        {
+            let mut req = HyperRequest::new(
+                method.clone(),
+                test_env.to_uri(format!("{}{}", test_env.base_uri(), path)),
+            );
+            req.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+            req.headers_mut().set_raw("X-Signature", "sig");
+            req.set_body(body);
+            test_env.with_client(|c| c.request(req));
+        }
+        let captured = test_env.get_bin_requests(&bin.id).unwrap();
+        let replay_uri = test_env.to_uri(format!(
+            "{}/rusqbins/{}/replay",
+            test_env.base_uri(),
+            bin.id
+        ));
+        let replay = |test_env: &mut TestEnv, body: serde_json::Value| {
+            let mut req = HyperRequest::new(Method::Post, replay_uri.clone());
+            req.set_body(body.to_string());
+            let resp = test_env.with_client(|c| c.request(req));
+            (resp.status(), test_env.get_body(resp))
+        };
+
+        let target_base = format!("{}/{}", test_env.base_uri(), target.id);
+        let (status, body) = replay(&mut test_env, json!({ "target": target_base }));
+        assert_eq!(status, StatusCode::Ok);
+        let outcomes: Vec<ReplayOutcome> = serde_json::from_str(&body).unwrap();
+        assert_eq!(outcomes.len(), 3);
+        for (outcome, request) in outcomes.iter().zip(&captured) {
+            assert_eq!(outcome.request_id, request.id);
+            assert_eq!(outcome.url, format!("{}{}", target_base, request.path));
+            assert_eq!((outcome.status, &outcome.error), (Some(200), &None));
+        }
+        let replayed = test_env.get_bin_requests(&target.id).unwrap();
+        assert_eq!(replayed.len(), 3);
+        for (replayed, original) in replayed.iter().zip(&captured) {
+            assert_eq!(replayed.method, original.method);
+            assert_eq!(replayed.path, original.path);
+            assert_eq!(replayed.body, original.body);
+            assert_eq!(
+                replayed.headers.get("X-Signature"),
+                Some(&vec!["sig".to_owned()])
+            );
+        }
+
+        // Replaying a subset, by id or by match
+        let (_, body) = replay(
+            &mut test_env,
+            json!({ "target": target_base, "request_ids": [captured[2].id.value()] }),
+        );
+        let outcomes: Vec<ReplayOutcome> = serde_json::from_str(&body).unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].request_id, captured[2].id);
+        let (_, body) = replay(
+            &mut test_env,
+            json!({ "target": target_base, "match": { "method": "PUT" } }),
+        );
+        let outcomes: Vec<ReplayOutcome> = serde_json::from_str(&body).unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].request_id, captured[1].id);
+
+        // Failures are reported per request
+        let (status, body) = replay(
+            &mut test_env,
+            json!({ "target": format!("http://127.0.0.1:{}", next_port()) }),
+        );
+        assert_eq!(status, StatusCode::Ok);
+        let outcomes: Vec<ReplayOutcome> = serde_json::from_str(&body).unwrap();
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes.iter().all(|o| o.status.is_none() && o.error.is_some()));
+
+        let (status, _) = replay(&mut test_env, json!({ "target": "https://example.com" }));
+        assert_eq!(status, StatusCode::BadRequest);
+
+        let mut req = HyperRequest::new(
+            Method::Post,
+            test_env.to_uri(format!(
+                "{}/rusqbins/{}/replay",
+                test_env.base_uri(),
+                Id::random()
+            )),
+        );
+        req.set_body(json!({ "target": target_base }).to_string());
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::NotFound);
+    })
+}
+
+#[test]
+fn test_unreachable_bin_upstream() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let config: BinConfig = serde_json::from_value(json!({
+            "upstream": format!("http://127.0.0.1:{}", next_port())
+        })).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+
+        let mut req = HyperRequest::new(
+            Method::Get,
+            test_env.to_uri(format!("{}/anything", test_env.base_uri())),
+        );
+        req.headers_mut().set(XRusqBinId(bin.id.value().to_owned()));
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::BadGateway);
+        let decoded: HashMap<String, String> =
+            serde_json::from_str(&test_env.get_body(resp)).unwrap();
+        assert_eq!(decoded.get("error"), Some(&"upstream_error".to_owned()));
+
+        // The request is still captured, along with the error sent back
+        let captured = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].response.as_ref().map(|r| r.status), Some(502));
+    })
+}
+
+#[test]
+fn test_bin_scenarios() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let config: BinConfig = serde_json::from_str(
+            r#"{
+              "response": { "status": 202 },
+              "scenarios": [
+                {
+                  "name": "flaky-payments",
+                  "match": { "method": "POST", "path": "^/payments$" },
+                  "initial_state": "down",
+                  "states": {
+                    "down": { "response": { "status": 503 }, "next": "still-down" },
+                    "still-down": { "response": { "status": 503 }, "next": "up" },
+                    "up": { "response": { "status": 200, "body": "paid" } }
+                  }
+                }
+              ]
+            }"#,
+        ).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+
+        let scenarios_uri = format!("{}/rusqbins/{}/scenarios", test_env.base_uri(), bin.id);
+        let states = |test_env: &mut TestEnv, method: Method| {
+            let uri = test_env.to_uri(scenarios_uri.clone());
+            let resp = test_env.with_client(|c| c.request(HyperRequest::new(method, uri)));
+            let body = test_env.get_body(resp);
+            serde_json::from_str::<HashMap<String, String>>(&body).unwrap()["flaky-payments"]
+                .clone()
+        };
+        assert_eq!(states(&mut test_env, Method::Get), "down");
+
+        let pay = |test_env: &mut TestEnv, path: &'static str| {
+            let resp = test_env.parallel_requests(&bin.id, &vec![capture(Method::Post, path)], 1);
+            resp[0].status()
+        };
+        assert_eq!(pay(&mut test_env, "/payments"), StatusCode::ServiceUnavailable);
+        assert_eq!(pay(&mut test_env, "/refunds"), StatusCode::Accepted);
+        assert_eq!(pay(&mut test_env, "/payments"), StatusCode::ServiceUnavailable);
+        assert_eq!(pay(&mut test_env, "/payments"), StatusCode::Ok);
+        assert_eq!(pay(&mut test_env, "/payments"), StatusCode::Ok);
+        assert_eq!(states(&mut test_env, Method::Get), "up");
+
+        assert_eq!(states(&mut test_env, Method::Delete), "down");
+        assert_eq!(pay(&mut test_env, "/payments"), StatusCode::ServiceUnavailable);
+        assert_eq!(states(&mut test_env, Method::Get), "still-down");
+
+        // Replacing the config starts its scenarios over
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+        assert_eq!(states(&mut test_env, Method::Get), "down");
+
+        let missing = test_env.to_uri(format!(
+            "{}/rusqbins/{}/scenarios",
+            test_env.base_uri(),
+            Id::random()
+        ));
+        let resp = test_env.with_client(|c| c.request(HyperRequest::new(Method::Get, missing)));
+        assert_eq!(resp.status(), StatusCode::NotFound);
+    })
+}
+
+/// Sends a request to be captured by the given bin, returning how long it took to get a response
+/// and read its body.
+fn timed_capture<T>(
+    test_env: &mut TestEnv<T>,
+    bin_id: &Id,
+) -> Result<(StatusCode, String, Duration), hyper::Error>
+where
+    T: Bins + Send + 'static,
+{
+    let mut req = HyperRequest::new(
+        Method::Get,
+        test_env.to_uri(format!("{}/slow", test_env.base_uri())),
+    );
+    req.headers_mut().set(XRusqBinId(bin_id.value().to_owned()));
+    let start = Instant::now();
+    let resp = test_env.try_with_client(|c| c.request(req))?;
+    let status = resp.status();
+    let body = test_env.get_body(resp);
+    Ok((status, body, start.elapsed()))
+}
+
+#[test]
+fn test_bin_fault_injection() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let mut config: BinConfig = serde_json::from_str(
+            r#"{
+              "response": { "status": 200, "body": "abcd" },
+              "faults": {
+                "delay_ms": 200,
+                "slow_body": { "chunk_size": 2, "chunk_delay_ms": 100 }
+              }
+            }"#,
+        ).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+        let (status, body, elapsed) = timed_capture(&mut test_env, &bin.id).unwrap();
+        assert_eq!(status, StatusCode::Ok);
+        assert_eq!(body, "abcd");
+        assert!(elapsed >= Duration::from_millis(400));
+
+        config.faults = serde_json::from_str(r#"{ "error_percent": 100, "error_status": 503 }"#)
+            .unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+        let (status, body, _) = timed_capture(&mut test_env, &bin.id).unwrap();
+        assert_eq!(status, StatusCode::ServiceUnavailable);
+        assert_eq!(body, "");
+
+        config.faults = serde_json::from_str(r#"{ "drop_percent": 100 }"#).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+        assert!(timed_capture(&mut test_env, &bin.id).is_err());
+
+        // Requests are captured no matter what
+        assert_eq!(test_env.get_bin_requests(&bin.id).unwrap().len(), 3);
+    })
+}
+
+#[test]
+fn test_path_based_bin_routing() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        for path in &["/hooks/github?event=push", ""] {
+            let req = HyperRequest::new(
+                Method::Post,
+                test_env.to_uri(format!("{}/{}{}", test_env.base_uri(), bin.id, path)),
+            );
+            let resp = test_env.with_client(|c| c.request(req));
+            assert_eq!(resp.status(), StatusCode::Ok);
+        }
+
+        let requests = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path, "/hooks/github?event=push");
+        assert_eq!(
+            requests[0].query_string.get("event"),
+            Some(&vec!["push".to_owned()])
+        );
+        assert_eq!(requests[1].path, "/");
+    })
+}
+
+#[test]
+fn test_path_based_bin_routing_with_prefix() {
+    let config = ServerConfig {
+        bin_path_prefix: "/b/".to_owned(),
+        ..ServerConfig::default()
+    };
+    run_with_server_config(InMemoryBins::new(), config, |mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let unprefixed = HyperRequest::new(
+            Method::Get,
+            test_env.to_uri(format!("{}/{}/hook", test_env.base_uri(), bin.id)),
+        );
+        let resp = test_env.with_client(|c| c.request(unprefixed));
+        assert_eq!(resp.status(), StatusCode::BadRequest);
+
+        let prefixed = HyperRequest::new(
+            Method::Get,
+            test_env.to_uri(format!("{}/b/{}/hook", test_env.base_uri(), bin.id)),
+        );
+        let resp = test_env.with_client(|c| c.request(prefixed));
+        assert_eq!(resp.status(), StatusCode::Ok);
+
+        let requests = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/hook");
+    })
+}
+
+#[test]
+fn test_host_based_bin_routing() {
+    let config = ServerConfig {
+        bin_base_domain: Some("bins.local".to_owned()),
+        ..ServerConfig::default()
+    };
+    run_with_server_config(InMemoryBins::new(), config, |mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let port = test_env.server.port as u16;
+        for host in &[format!("{}.bins.local", bin.id), format!("{}.other.local", bin.id)] {
+            let mut req = HyperRequest::new(
+                Method::Get,
+                test_env.to_uri(format!("{}/hook?a=b", test_env.base_uri())),
+            );
+            req.headers_mut().set(Host::new(host.to_owned(), Some(port)));
+            let resp = test_env.with_client(|c| c.request(req));
+            let expected = if host.ends_with(".bins.local") {
+                StatusCode::Ok
+            } else {
+                StatusCode::BadRequest
+            };
+            assert_eq!(resp.status(), expected);
+        }
+
+        let requests = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/hook?a=b");
+    })
+}
+
+#[test]
+fn test_query_param_bin_routing() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let queries = [
+            format!("code=abc&rusqbin_id={}&state=xyz", bin.id),
+            format!("rusqbin_id={}", bin.id),
+            format!("redirect=a%20b%2Fc&rusqbin_id={}&scope=a+b/c&flag", bin.id),
+        ];
+        for query in &queries {
+            let req = HyperRequest::new(
+                Method::Get,
+                test_env.to_uri(format!("{}/oauth/callback?{}", test_env.base_uri(), query)),
+            );
+            let resp = test_env.with_client(|c| c.request(req));
+            assert_eq!(resp.status(), StatusCode::Ok);
+        }
+
+        let requests = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].path, "/oauth/callback?code=abc&state=xyz");
+        assert_eq!(requests[0].query_string.len(), 2);
+        assert!(!requests[0].query_string.contains_key("rusqbin_id"));
+        assert_eq!(requests[1].path, "/oauth/callback");
+        assert!(requests[1].query_string.is_empty());
+        // The rest of the query is recorded as it was sent, not re-encoded
+        assert_eq!(requests[2].path, "/oauth/callback?redirect=a%20b%2Fc&scope=a+b/c&flag");
+        assert_eq!(requests[2].query_string["redirect"], vec!["a b/c".to_owned()]);
+    })
+}
+
+#[test]
+fn test_waiting_for_requests() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        test_env.parallel_requests(&bin.id, &vec![capture(Method::Post, "/early")], 1);
+
+        let wait_uri = test_env.to_uri(format!(
+            "{}/rusqbins/{}/requests/wait?count=3&method=post&timeout=10",
+            test_env.base_uri(),
+            bin.id
+        ));
+        let base_uri = test_env.base_uri();
+        let handle = test_env.handle();
+        let id = bin.id.value().to_owned();
+        let (resp, _) = test_env.with_client(|c| {
+            let wait = c.request(HyperRequest::new(Method::Get, wait_uri));
+            let later_client = c.clone();
+            let later = Timeout::new(Duration::from_millis(300), &handle)
+                .unwrap()
+                .map_err(hyper::Error::Io)
+                .and_then(move |_| {
+                    let sends: Vec<_> = vec![Method::Get, Method::Post, Method::Post]
+                        .into_iter()
+                        .map(|method| {
+                            let mut req = HyperRequest::new(
+                                method,
+                                format!("{}/late", base_uri).parse().unwrap(),
+                            );
+                            req.headers_mut().set(XRusqBinId(id.clone()));
+                            later_client.request(req)
+                        })
+                        .collect();
+                    future::join_all(sends)
+                });
+            wait.join(later)
+        });
+        assert_eq!(resp.status(), StatusCode::Ok);
+        let body = test_env.get_body(resp);
+        let requests: Vec<Request> = serde_json::from_str(&body).unwrap();
+        let paths: Vec<&str> = requests.iter().map(|r| &*r.path).collect();
+        assert_eq!(paths, vec!["/early", "/late", "/late"]);
+        assert!(requests.iter().all(|r| r.method == "POST"));
+    })
+}
+
+#[test]
+fn test_waiting_for_requests_errors() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let mut wait = |query: &str| {
+            let req = HyperRequest::new(
+                Method::Get,
+                test_env.to_uri(format!(
+                    "{}/rusqbins/{}/requests/wait?{}",
+                    test_env.base_uri(),
+                    bin.id,
+                    query
+                )),
+            );
+            test_env.with_client(|c| c.request(req)).status()
+        };
+        assert_eq!(wait("timeout=1"), StatusCode::RequestTimeout);
+        assert_eq!(wait("count=0"), StatusCode::BadRequest);
+        assert_eq!(wait("timeout=301"), StatusCode::BadRequest);
+        assert_eq!(wait("timeout=18446744073709551615"), StatusCode::BadRequest);
+        assert_eq!(wait("path=("), StatusCode::BadRequest);
+    })
+}
+
+#[test]
+fn test_streaming_requests() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        test_env.parallel_requests(&bin.id, &vec![capture(Method::Get, "/old")], 1);
+        let since = test_env.get_bin_requests(&bin.id).unwrap()[0].time;
+
+        let stream_uri = test_env.to_uri(format!(
+            "{}/rusqbins/{}/stream?since={}",
+            test_env.base_uri(),
+            bin.id,
+            since
+        ));
+        let base_uri = test_env.base_uri();
+        let handle = test_env.handle();
+        let id = bin.id.clone();
+        let (resp, _) = test_env.with_client(|c| {
+            let stream = c.request(HyperRequest::new(Method::Get, stream_uri));
+            let later_client = c.clone();
+            // Capture a request, then delete the bin to end the stream
+            let later = Timeout::new(Duration::from_millis(300), &handle)
+                .unwrap()
+                .map_err(hyper::Error::Io)
+                .and_then(move |_| {
+                    let mut req = HyperRequest::new(
+                        Method::Post,
+                        format!("{}/new", base_uri).parse().unwrap(),
+                    );
+                    req.headers_mut().set(XRusqBinId(id.value().to_owned()));
+                    later_client.request(req).and_then(move |_| {
+                        later_client.request(HyperRequest::new(
+                            Method::Delete,
+                            format!("{}/rusqbins/{}", base_uri, id).parse().unwrap(),
+                        ))
+                    })
+                });
+            stream.join(later)
+        });
+        assert_eq!(resp.status(), StatusCode::Ok);
+        let body = test_env.get_body(resp);
+        let paths: Vec<String> = body.lines()
+            .filter(|line| line.starts_with("data: "))
+            .map(|line| {
+                let req: Request = serde_json::from_str(&line["data: ".len()..]).unwrap();
+                req.path
+            })
+            .collect();
+        assert_eq!(paths, vec!["/old".to_owned(), "/new".to_owned()]);
+        assert_eq!(body.matches("event: request\n").count(), 2);
+    })
+}
+
+#[test]
+fn test_filtering_requests() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let mut tenant = Headers::new();
+        tenant.set(XFlubble("acme".to_owned()));
+        test_env.parallel_requests(
+            &bin.id,
+            &vec![
+                ServerRequest {
+                    method: Method::Post,
+                    path: "/orders/1",
+                    body: Some(r#"{"order": {"status": "paid"}}"#),
+                    headers: tenant,
+                },
+                capture(Method::Get, "/orders/2?page=3"),
+                ServerRequest {
+                    method: Method::Post,
+                    path: "/users",
+                    body: Some("hello there"),
+                    headers: Headers::new(),
+                },
+            ],
+            1,
+        );
+        let mut filtered = |query: &str| {
+            let mut paths = test_env.get_filtered_bin_paths(&bin.id, query).unwrap();
+            paths.sort();
+            paths
+        };
+        assert_eq!(filtered("").len(), 3);
+        assert_eq!(filtered("method=POST"), vec!["/orders/1", "/users"]);
+        assert_eq!(filtered("path_glob=/orders/*"), vec!["/orders/1", "/orders/2?page=3"]);
+        assert_eq!(filtered("path=%5E%2Fu"), vec!["/users"]);
+        assert_eq!(filtered("header=x-flubble"), vec!["/orders/1"]);
+        assert_eq!(filtered("header=x-flubble:other"), Vec::<String>::new());
+        assert_eq!(filtered("query=page:3"), vec!["/orders/2?page=3"]);
+        assert_eq!(filtered("body=hello"), vec!["/users"]);
+        assert_eq!(filtered("json=/order/status:paid"), vec!["/orders/1"]);
+        assert_eq!(filtered("until=0"), Vec::<String>::new());
+
+        let uri = test_env.to_uri(format!(
+            "{}/rusqbins/{}/requests?since=soon",
+            test_env.base_uri(),
+            bin.id
+        ));
+        let resp = test_env.with_client(|c| c.request(HyperRequest::new(Method::Get, uri)));
+        assert_eq!(resp.status(), StatusCode::BadRequest);
+    })
+}
+
+#[test]
+fn test_paging_through_requests() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        for path in &["/1", "/2", "/3", "/4", "/5"] {
+            test_env.parallel_requests(&bin.id, &vec![capture(Method::Get, path)], 1);
+        }
+        // Follows Link headers from the given query, returning the paths on each page
+        let mut pages = |query: &str| {
+            let mut pages = Vec::new();
+            let mut next = Some(format!("/rusqbins/{}/requests?{}", bin.id, query));
+            while let Some(uri) = next {
+                let uri = test_env.to_uri(format!("{}{}", test_env.base_uri(), uri));
+                let resp = test_env.with_client(|c| c.request(HyperRequest::new(Method::Get, uri)));
+                assert_eq!(resp.status(), StatusCode::Ok);
+                next = resp.headers().get_raw("Link").map(|link| {
+                    let link = String::from_utf8(link.one().unwrap().to_vec()).unwrap();
+                    assert!(link.ends_with(r#">; rel="next""#));
+                    link[1..link.find('>').unwrap()].to_owned()
+                });
+                let requests: Vec<Request> = serde_json::from_str(&test_env.get_body(resp)).unwrap();
+                pages.push(requests.into_iter().map(|r| r.path).collect::<Vec<String>>());
+            }
+            pages
+        };
+        assert_eq!(
+            pages("limit=2"),
+            vec![vec!["/1", "/2"], vec!["/3", "/4"], vec!["/5"]]
+        );
+        assert_eq!(
+            pages("limit=2&order=desc"),
+            vec![vec!["/5", "/4"], vec!["/3", "/2"], vec!["/1"]]
+        );
+        assert_eq!(pages("limit=5"), vec![vec!["/1", "/2", "/3", "/4", "/5"]]);
+        assert_eq!(
+            pages("limit=18446744073709551615"),
+            vec![vec!["/1", "/2", "/3", "/4", "/5"]]
+        );
+        assert_eq!(
+            pages("path=%5B135%5D&limit=18446744073709551615"),
+            vec![vec!["/1", "/3", "/5"]]
+        );
+        assert!(pages("offset=18446744073709551615")[0].is_empty());
+        assert_eq!(pages("offset=1&limit=1&order=desc")[0], vec!["/4"]);
+        assert_eq!(
+            pages("path=%5B135%5D&limit=2"),
+            vec![vec!["/1", "/3"], vec!["/5"]]
+        );
+        assert_eq!(
+            pages("path=%5B135%5D&offset=1&limit=1&order=desc"),
+            vec![vec!["/3"], vec!["/1"]]
+        );
+
+        for bad in &["limit=0", "offset=-1", "order=sideways", "cursor=x"] {
+            let uri = test_env.to_uri(format!(
+                "{}/rusqbins/{}/requests?{}",
+                test_env.base_uri(),
+                bin.id,
+                bad
+            ));
+            let resp = test_env.with_client(|c| c.request(HyperRequest::new(Method::Get, uri)));
+            assert_eq!(resp.status(), StatusCode::BadRequest);
+        }
+    })
+}
+
+#[test]
+fn test_single_request_endpoints() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        test_env.parallel_requests(&bin.id, &vec![capture(Method::Get, "/first")], 1);
+        test_env.parallel_requests(&bin.id, &vec![capture(Method::Post, "/second")], 1);
+        let requests = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(
+            requests.iter().map(|r| r.seq).collect::<Vec<usize>>(),
+            vec![1, 2]
+        );
+        assert_ne!(requests[0].id, requests[1].id);
+
+        let request_uri = |request_id: &Id| {
+            format!("{}/rusqbins/{}/requests/{}", test_env.base_uri(), bin.id, request_id)
+        };
+        let (first_uri, missing_uri) = (request_uri(&requests[0].id), request_uri(&Id::random()));
+        let mut call = |method: Method, uri: &str| {
+            let uri = test_env.to_uri(uri.to_owned());
+            let resp = test_env.with_client(|c| c.request(HyperRequest::new(method, uri)));
+            let status = resp.status();
+            (status, test_env.get_body(resp))
+        };
+
+        let (status, body) = call(Method::Get, &first_uri);
+        assert_eq!(status, StatusCode::Ok);
+        let found: Request = serde_json::from_str(&body).unwrap();
+        assert_eq!(found, requests[0]);
+        assert_eq!(call(Method::Get, &missing_uri).0, StatusCode::NotFound);
+
+        assert_eq!(call(Method::Delete, &first_uri).0, StatusCode::Ok);
+        assert_eq!(call(Method::Get, &first_uri).0, StatusCode::NotFound);
+        assert_eq!(call(Method::Delete, &first_uri).0, StatusCode::NotFound);
+        assert_eq!(test_env.get_bin_requests(&bin.id).unwrap(), vec![requests[1].clone()]);
+    })
+}
+
+#[test]
+fn test_clear_bin_requests() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let config: BinConfig = serde_json::from_str(r#"{"response": {"status": 201}}"#).unwrap();
+        assert!(test_env.set_bin_config(&bin.id, &config).unwrap());
+        test_env.parallel_requests(&bin.id, &vec![capture(Method::Get, "/before")], 2);
+
+        assert!(test_env.clear_bin_requests(&bin.id).unwrap());
+        assert!(test_env.get_bin_requests(&bin.id).unwrap().is_empty());
+        assert_eq!(test_env.get_bin_config(&bin.id).unwrap(), config);
+        assert!(!test_env.clear_bin_requests(&Id::random()).unwrap());
+
+        // The bin keeps capturing and mocking, carrying on from where its sequence numbers left off
+        let responses = test_env.parallel_requests(&bin.id, &vec![capture(Method::Get, "/after")], 1);
+        assert_eq!(responses[0].status(), StatusCode::Created);
+        let requests = test_env.get_bin_requests(&bin.id).unwrap();
+        assert_eq!(
+            requests.iter().map(|r| (r.path.as_str(), r.seq)).collect::<Vec<_>>(),
+            vec![("/after", 3)]
+        );
+        let summary = test_env.get_bin_summary(&bin.id).unwrap();
+        assert_eq!(summary.request_count, 1);
+        assert_eq!(summary.total_request_count, 3);
+    })
+}
+
+#[test]
+fn test_verify_expected_calls() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        test_env.parallel_requests(
+            &bin.id,
+            &vec![capture(Method::Post, "/webhooks"), capture(Method::Get, "/health")],
+            1,
+        );
+
+        let verify_uri = format!("{}/rusqbins/{}/verify", test_env.base_uri(), bin.id);
+        let missing_uri = format!("{}/rusqbins/{}/verify", test_env.base_uri(), Id::random());
+        let mut verify = |uri: &str, expectation: &str| {
+            let mut req = HyperRequest::new(Method::Post, test_env.to_uri(uri.to_owned()));
+            req.set_body(expectation.to_owned());
+            let resp = test_env.with_client(|c| c.request(req));
+            let status = resp.status();
+            let body = test_env.get_body(resp);
+            (status, serde_json::from_str::<serde_json::Value>(&body).ok())
+        };
+
+        let (status, passed) = verify(
+            &verify_uri,
+            r#"{"match": {"method": "POST", "path": "^/webhooks$"}, "count": {"exactly": 1}}"#,
+        );
+        assert_eq!(status, StatusCode::Ok);
+        let passed = passed.unwrap();
+        assert_eq!(passed["passed"], true);
+        assert_eq!(passed["matched"], 1);
+
+        let (status, failed) = verify(
+            &verify_uri,
+            r#"{"match": {"method": "PUT", "path": "^/webhooks$"}, "count": {"at_least": 1}}"#,
+        );
+        assert_eq!(status, StatusCode::Ok);
+        let failed = failed.unwrap();
+        assert_eq!(failed["passed"], false);
+        assert_eq!(failed["matched"], 0);
+        let closest = &failed["near_misses"][0];
+        assert_eq!(closest["request"]["path"], "/webhooks");
+        assert_eq!(
+            closest["mismatches"],
+            json!([{"field": "method", "name": null, "expected": "PUT", "actual": "POST"}])
+        );
+        assert_eq!(failed["near_misses"][1]["request"]["path"], "/health");
+
+        let (status, _) = verify(&verify_uri, r#"{"match": {"path": "("}}"#);
+        assert_eq!(status, StatusCode::BadRequest);
+        let (status, _) = verify(&verify_uri, r#"{"count": {"roughly": 2}}"#);
+        assert_eq!(status, StatusCode::BadRequest);
+        assert_eq!(verify(&missing_uri, "{}").0, StatusCode::NotFound);
+    })
+}
+
+#[test]
+fn test_websocket_feed() {
+    let ws_port = next_port();
+    let config = ServerConfig {
+        websocket_port: Some(ws_port),
+        ..ServerConfig::default()
+    };
+    run_with_server_config(InMemoryBins::new(), config, |mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let other_bin = test_env.create_bin().unwrap();
+        let handle = test_env.handle();
+        let addr = format!("127.0.0.1:{}", ws_port).parse().unwrap();
+        let connect = |path: String| {
+            let url = Url::parse(&format!("ws://localhost:{}{}", ws_port, path)).unwrap();
+            TcpStream::connect(&addr, &handle)
+                .map_err(WsError::Io)
+                .and_then(move |tcp| client_async(url, tcp))
+                .map(|(ws, _)| ws)
+        };
+        let next_message = |ws: WebSocketStream<TcpStream>| {
+            Timeout::new(Duration::from_secs(5), &handle)
+                .unwrap()
+                .map_err(WsError::Io)
+                .select2(ws.into_future().map_err(|(e, _)| e))
+                .map_err(|e| e.split().0)
+                .and_then(|done| match done {
+                    future::Either::B(((Some(Message::Text(text)), ws), _)) => {
+                        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+                        Ok((json, ws))
+                    }
+                    _ => Err(WsError::Protocol("no message".into())),
+                })
+        };
+
+        // Hear about POSTs to any bin; the bad filter's reply shows the first one took effect
+        let all_bins = test_env.run(connect("/rusqbins/ws".to_owned())).unwrap();
+        let all_bins = test_env
+            .run(all_bins.send(Message::Text(r#"{"method": "POST"}"#.to_owned())))
+            .unwrap();
+        let all_bins = test_env
+            .run(all_bins.send(Message::Text(r#"{"path": "("}"#.to_owned())))
+            .unwrap();
+        let (reply, all_bins) = test_env.run(next_message(all_bins)).unwrap();
+        assert_eq!(reply["error"], "bad_request");
+
+        // Hear about requests to /yes paths of one bin
+        let one_bin = test_env
+            .run(connect(format!("/rusqbins/{}/ws?path=^/yes", bin.id)))
+            .unwrap();
+
+        test_env.parallel_requests(&bin.id, &vec![capture(Method::Get, "/no")], 1);
+        test_env.parallel_requests(&bin.id, &vec![capture(Method::Get, "/yes")], 1);
+        test_env.parallel_requests(&other_bin.id, &vec![capture(Method::Post, "/posted")], 1);
+
+        let (event, _) = test_env.run(next_message(all_bins)).unwrap();
+        assert_eq!(event["bin_id"], other_bin.id.value());
+        assert_eq!(event["request"]["path"], "/posted");
+        let (event, _) = test_env.run(next_message(one_bin)).unwrap();
+        assert_eq!(event["bin_id"], bin.id.value());
+        assert_eq!(event["request"]["path"], "/yes");
+
+        // Bins that don't exist can't be followed
+        assert!(
+            test_env
+                .run(connect(format!("/rusqbins/{}/ws", Id::random())))
+                .is_err()
+        );
+    })
+}
+
+/// A request for a bin to capture, as sent by parallel_requests.
+fn capture(method: Method, path: &'static str) -> ServerRequest<'static> {
+    ServerRequest {
+        method,
+        path,
+        body: None,
+        headers: Headers::new(),
+    }
+}
+
+#[test]
+fn test_bin_config_errors() {
+    run_with_server(|mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let mut req = HyperRequest::new(
+            Method::Put,
+            test_env.to_uri(format!("{}/rusqbins/{}/config", test_env.base_uri(), bin.id)),
+        );
+        req.set_body("{ \"response\": { \"status\": 1000 } }");
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::BadRequest);
+
+        assert!(!test_env
+            .set_bin_config(&Id::random(), &BinConfig::default())
+            .unwrap());
+    })
+}
+
+/// Storage that fails at everything, for checking how the server reports storage errors.
+struct FailingBins;
+
+impl Bins for FailingBins {
+    fn create_bin_with_options(&mut self, _: &BinOptions) -> Result<BinSummary, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn delete_bin(&mut self, _: &Id) -> Result<DeleteBinStatus, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn get_bin(&self, _: &Id) -> Result<Option<Bin>, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn delete_request(&mut self, _: &Id, _: &Id) -> Result<DeleteRequestStatus, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn clear_bin(&mut self, _: &Id) -> Result<ClearBinStatus, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn record_response(
+        &mut self,
+        _: &Id,
+        _: &Id,
+        _: RecordedResponse,
+    ) -> Result<RecordResponseStatus, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn get_bin_summary(&self, _: &Id) -> Result<Option<BinSummary>, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn get_bin_summaries(&self) -> Result<HashMap<Id, BinSummary>, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn get_bin_config(&self, _: &Id) -> Result<Option<BinConfig>, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn set_bin_config(&mut self, _: &Id, _: BinConfig) -> Result<SetBinConfigStatus, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+
+    fn insert_request(&mut self, _: &Id, _: Request) -> Result<InsertRequestStatus, Error> {
+        Err(Error::storage("disk on fire"))
+    }
+}
+
+#[test]
+fn test_storage_errors_are_reported_as_json() {
+    run_with_server_using(FailingBins, |mut test_env| {
+        let req = HyperRequest::new(
+            Method::Post,
+            test_env.to_uri(format!("{}/rusqbins", test_env.base_uri())),
+        );
+        let resp = test_env.with_client(|c| c.request(req));
+        assert_eq!(resp.status(), StatusCode::ServiceUnavailable);
+
+        let string = test_env.get_body(resp);
+        let decoded: HashMap<String, String> = serde_json::from_str(&string).unwrap();
+        assert_eq!(decoded.get("error"), Some(&"storage_error".to_owned()));
+        assert!(decoded.get("message").unwrap().contains("disk on fire"));
+    })
+}
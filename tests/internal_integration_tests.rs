@@ -8,7 +8,11 @@ use self::rusqbin::models::BinSummary;
 use hyper::header::{Headers, Header};
 use hyper::Method;
 
-use rusqbin::models::Request;
+use std::thread;
+use std::time::Duration;
+
+use rusqbin::models::{Body, Request};
+use rusqbin::server::ServerConfig;
 use rusqbin::storage::*;
 
 #[cfg(test)]
@@ -20,7 +24,13 @@ use common::*;
 #[test]
 fn test_list_empty() {
     run_with_server(|test_env| {
-        let ref bins = test_env.server.storage.lock().unwrap().get_bin_summaries();
+        let bins = test_env
+            .server
+            .storage
+            .lock()
+            .unwrap()
+            .get_bin_summaries()
+            .unwrap();
         assert!(bins.is_empty());
     })
 }
@@ -31,7 +41,37 @@ fn test_creating_bin() {
     run_with_server(|mut test_env| {
         let bin = test_env.create_bin().unwrap();
         let ref storage = test_env.server.storage.lock().unwrap();
-        assert!(storage.get_bin_summary(&bin.id).is_some());
+        assert!(storage.get_bin_summary(&bin.id).unwrap().is_some());
+    })
+}
+
+#[test]
+fn test_creating_bin_with_default_max_requests() {
+    let config = ServerConfig {
+        default_max_requests: Some(2),
+        ..ServerConfig::default()
+    };
+    run_with_server_config(InMemoryBins::new(), config, |mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        let storage = test_env.server.storage.lock().unwrap();
+        let summary = storage.get_bin_summary(&bin.id).unwrap().unwrap();
+        assert_eq!(summary.max_requests, Some(2));
+    })
+}
+
+#[test]
+fn test_expired_bins_are_reaped() {
+    let config = ServerConfig {
+        default_ttl_secs: Some(1),
+        reap_interval: Duration::from_millis(100),
+        ..ServerConfig::default()
+    };
+    run_with_server_config(InMemoryBins::new(), config, |mut test_env| {
+        let bin = test_env.create_bin().unwrap();
+        assert_eq!(bin.expires_at, Some(bin.created_at + 1000));
+        thread::sleep(Duration::from_millis(1500));
+        let storage = test_env.server.storage.lock().unwrap();
+        assert!(storage.get_bin_summary(&bin.id).unwrap().is_none());
     })
 }
 
@@ -41,7 +81,7 @@ fn test_deleting_existing_bin() {
         let bin = test_env.create_bin().unwrap();
         let _deleted = test_env.delete_bin(&bin.id).unwrap();
         let ref storage = test_env.server.storage.lock().unwrap();
-        assert!(storage.get_bin_summary(&bin.id).is_none());
+        assert!(storage.get_bin_summary(&bin.id).unwrap().is_none());
     })
 }
 
@@ -74,7 +114,7 @@ fn test_requesting_bin_summary() {
         test_env.parallel_requests(&bin_id, &requests, 2);
 
         let ref storage = test_env.server.storage.lock().unwrap();
-        let bin_summary: BinSummary = storage.get_bin_summary(&bin_id).unwrap();
+        let bin_summary: BinSummary = storage.get_bin_summary(&bin_id).unwrap().unwrap();
         assert_eq!(bin_summary.request_count, requests.len() * 2);
     })
 }
@@ -104,7 +144,7 @@ fn test_requesting_bin_requests() {
         test_env.parallel_requests(&bin_id, &requests, 1);
 
         let ref storage = test_env.server.storage.lock().unwrap();
-        let requests: &Vec<Request> = storage.get_bin(&bin_id).unwrap();
+        let requests: Vec<Request> = storage.get_bin(&bin_id).unwrap().unwrap();
         assert_eq!(requests.len(), 1);
 
         let req: &Request = &requests[0];
@@ -122,7 +162,7 @@ fn test_requesting_bin_requests() {
                 .is_some()
         );
 
-        assert_eq!(req.body, Some("hey there.".to_owned()));
+        assert_eq!(req.body, Some(Body::from("hey there.")));
         assert_eq!(req.method, Method::Post.as_ref());
 
     })